@@ -0,0 +1,307 @@
+use crate::*;
+use ansi_term::Colour::{Red, White};
+use engine::{Engine, SearchHandle, SearchResult};
+use input::{read_line, read_line_timeout};
+use notation::{move_to_san, parse_move};
+use settings::Settings;
+use std::io::Write;
+
+// Deepest search of the continuous analysis, it keeps going until then or until the user
+// enters something
+const MAX_ANALYSIS_DEPTH: i32 = 12;
+
+// A position explored on the analysis board and the moves played from it
+struct Analysis {
+    // Where the line starts, usually the starting position
+    start: (Board, bool),
+    // Number of the first move, taken from the FEN when starting elsewhere
+    first_move: usize,
+    // The moves of the line being explored, including those after the current position
+    moves: Vec<Move>,
+    // Number of moves of the line that are played on the board
+    current: usize,
+    // Event and players of a loaded game
+    title: Option<String>,
+    flipped: bool,
+    lines: usize,
+    // Shown below the board until the next command
+    message: Option<String>,
+}
+
+impl Analysis {
+    // The position after the first n moves of the line and the side to move there
+    fn position(&self, n: usize) -> (Board, bool) {
+        let (mut board, mut is_white) = self.start;
+        for &(from, to) in &self.moves[..n] {
+            if move_piece(&mut board, from, to, is_white).is_err() {
+                break;
+            }
+            is_white = !is_white;
+        }
+        (board, is_white)
+    }
+
+    // The line in SAN with move numbers, the move leading to the current position in bold
+    fn move_list(&self) -> String {
+        let (mut board, mut is_white) = self.start;
+        let mut text = String::new();
+
+        // When Black moves first, White's next move already has the next number
+        let black_first = !self.start.1 as usize;
+        for (i, &move_) in self.moves.iter().enumerate() {
+            let number = self.first_move + (i + black_first) / 2;
+            if is_white {
+                text.push_str(&format!("{}. ", number));
+            } else if i == 0 {
+                text.push_str(&format!("{}... ", number));
+            }
+
+            let san = move_to_san(board, move_, is_white);
+            if i + 1 == self.current {
+                text.push_str(&format!("{} ", Red.bold().paint(san)));
+            } else {
+                text.push_str(&format!("{} ", san));
+            }
+
+            if move_piece(&mut board, move_.0, move_.1, is_white).is_err() {
+                break;
+            }
+            is_white = !is_white;
+        }
+
+        text
+    }
+
+    fn draw(&self, search: Option<(i32, &SearchResult)>) {
+        let (board, is_white) = self.position(self.current);
+        clear_draw(board, !self.flipped);
+
+        if let Some(title) = &self.title {
+            println!("{}", title);
+        }
+        if !self.moves.is_empty() {
+            println!("{}", self.move_list());
+        }
+        println!(
+            "{} to move, {} of {} moves played",
+            if is_white { "White" } else { "Black" },
+            self.current,
+            self.moves.len()
+        );
+
+        match check_for_mates(board) {
+            Some(winner) => println!("Checkmate, {} wins", winner.ctos()),
+            None => match search {
+                Some((_, result)) if result.lines.is_empty() || result.pv.is_empty() => {
+                    println!("No legal moves, stalemate")
+                }
+                Some((depth, result)) => {
+                    println!("Magnus at depth {}:", depth);
+                    print_lines(board, is_white, &result.lines);
+                }
+                None => println!("Magnus is analysing..."),
+            },
+        }
+
+        if let Some(message) = &self.message {
+            arrow_print(message, false);
+        }
+        print!("{} ", White.bold().paint(">>>"));
+        std::io::stdout().flush().unwrap();
+    }
+
+    // Play a move at the current position, replacing the rest of the line
+    fn play(&mut self, move_: Move) {
+        self.moves.truncate(self.current);
+        self.moves.push(move_);
+        self.current += 1;
+    }
+}
+
+// Explore a position freely: play moves for both sides, take them back and watch Magnus'
+// evaluation and best lines update while thinking about the next move
+pub fn analysis_board(settings: &Settings) {
+    clear_screen();
+    arrow_print("Which position do you want to analyse?", true);
+    arrow_print(
+        "(1) Starting position\n(2) From a FEN\n(3) From a PGN file\n",
+        false,
+    );
+
+    let mut analysis = loop {
+        match prompt().as_str() {
+            "1" => break new_analysis((Board::new(), true), 1, Vec::new(), None),
+            "2" => {
+                arrow_print("Enter the FEN:", true);
+                let text = prompt();
+                match fen::parse_fen(&text) {
+                    Ok(start) => {
+                        let first_move = fen::fullmove_number(&text);
+                        break new_analysis(start, first_move, Vec::new(), None);
+                    }
+                    Err(err) => arrow_print(&err, true),
+                }
+            }
+            "3" => {
+                arrow_print("Enter the path of the PGN file:", true);
+                match pgn::load(&prompt()) {
+                    Ok(game) => {
+                        let title = format!(
+                            "{} vs {} ({})",
+                            game.tag("White").unwrap_or("?"),
+                            game.tag("Black").unwrap_or("?"),
+                            game.tag("Event").unwrap_or("?")
+                        );
+                        let first_move = game.tag("FEN").map_or(1, fen::fullmove_number);
+                        break new_analysis(
+                            (game.start, game.start_white),
+                            first_move,
+                            game.moves,
+                            Some(title),
+                        );
+                    }
+                    Err(err) => arrow_print(&err, true),
+                }
+            }
+            _ => arrow_print("Invalid input!", true),
+        }
+    };
+
+    let engine = settings.engine();
+    loop {
+        let input = analyse_until_input(&engine, &analysis);
+        analysis.message = None;
+
+        let (board, is_white) = analysis.position(analysis.current);
+        let mut words = input.split_whitespace();
+        match (words.next().unwrap_or(""), words.next()) {
+            ("exit", _) => std::process::exit(0),
+            ("menu", _) => return,
+            ("back" | "undo", _) => analysis.current = analysis.current.saturating_sub(1),
+            ("forward" | "next", _) => {
+                analysis.current = (analysis.current + 1).min(analysis.moves.len())
+            }
+            ("start", _) => analysis.current = 0,
+            ("end", _) => analysis.current = analysis.moves.len(),
+            ("flip", _) => analysis.flipped = !analysis.flipped,
+            ("fen", _) => analysis.message = Some(fen::to_fen(&board, is_white)),
+            ("lines", Some(n)) => match n.parse::<usize>() {
+                Ok(n) if n > 0 => analysis.lines = n,
+                _ => analysis.message = Some("Usage: lines <NUMBER OF MOVES>".to_string()),
+            },
+            ("help", _) => analysis.message = Some(help().to_string()),
+            ("", _) => {}
+            (text, _) => match parse_move(board, text, is_white) {
+                Ok(move_) => analysis.play(move_),
+                Err(err) => analysis.message = Some(err),
+            },
+        }
+    }
+}
+
+fn new_analysis(
+    start: (Board, bool),
+    first_move: usize,
+    moves: Vec<Move>,
+    title: Option<String>,
+) -> Analysis {
+    Analysis {
+        start,
+        first_move,
+        moves,
+        current: 0,
+        title,
+        flipped: !start.1,
+        lines: DEFAULT_ANALYSIS_LINES,
+        message: Some(help().to_string()),
+    }
+}
+
+fn help() -> &'static str {
+    "Enter a move (e4, Nf3, g1f3), 'back', 'forward', 'start', 'end', 'flip', 'fen', 'lines <N>', 'help' or 'menu'."
+}
+
+fn prompt() -> String {
+    print!("{} ", White.bold().paint(">>>"));
+    std::io::stdout().flush().unwrap();
+    let mut input = String::new();
+    read_line(&mut input);
+    if input.trim() == "exit" {
+        std::process::exit(0);
+    }
+    input.trim().to_string()
+}
+
+// Search the current position one depth deeper at a time, redrawing after every depth,
+// until the user enters a line
+fn analyse_until_input(engine: &Engine, analysis: &Analysis) -> String {
+    let (board, is_white) = analysis.position(analysis.current);
+    analysis.draw(None);
+    if check_for_mates(board).is_some() {
+        let mut input = String::new();
+        read_line(&mut input);
+        return input.trim().to_string();
+    }
+
+    let mut engine = engine.clone();
+    engine.multi_pv = analysis.lines;
+    let mut depth = 1;
+    let mut handle: Option<SearchHandle> = Some(engine.start_search(board, depth, is_white));
+
+    loop {
+        if let Some(input) = read_line_timeout(std::time::Duration::from_millis(10)) {
+            if let Some(handle) = handle {
+                handle.stop();
+                handle.wait();
+            }
+            return input.trim().to_string();
+        }
+
+        if handle.as_ref().is_some_and(|handle| handle.is_finished()) {
+            let result = handle.take().unwrap().wait();
+            analysis.draw(Some((depth, &result)));
+
+            // Stop once the search can't get any better
+            let solved = result.pv.is_empty() || result.score.abs() > evaluation::MATE_SCORE - 1000;
+            if depth < MAX_ANALYSIS_DEPTH && !solved {
+                depth += 1;
+                handle = Some(engine.start_search(board, depth, is_white));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn move_list(fen: &str, moves: &[&str]) -> String {
+        let start = fen::parse_fen(fen).unwrap();
+        let mut analysis = new_analysis(start, fen::fullmove_number(fen), Vec::new(), None);
+        for text in moves {
+            let (board, is_white) = analysis.position(analysis.moves.len());
+            analysis
+                .moves
+                .push(parse_move(board, text, is_white).unwrap());
+        }
+        analysis.move_list()
+    }
+
+    #[test]
+    fn numbers_moves_from_white_or_black() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(move_list(start, &["e4", "e5", "Nf3"]), "1. e4 e5 2. Nf3 ");
+
+        let black = "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq - 0 1";
+        assert_eq!(
+            move_list(black, &["e5", "Nf3", "Nc6"]),
+            "1... e5 2. Nf3 Nc6 "
+        );
+    }
+
+    #[test]
+    fn numbers_moves_from_the_fen_fullmove_number() {
+        let fen = "4k3/8/8/8/8/8/8/R3K3 b Q - 3 40";
+        assert_eq!(move_list(fen, &["Kd7", "Ra7+"]), "40... Kd7 41. Ra7+ ");
+    }
+}
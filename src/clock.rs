@@ -0,0 +1,289 @@
+use crate::*;
+use std::time::{Duration, Instant};
+
+// How the bonus time of a time control is given
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Bonus {
+    // Fischer increment: added after every move
+    Increment,
+    // Bronstein delay: the time spent on a move is given back, up to the delay
+    Delay,
+}
+
+// Time for the whole game per player plus a bonus per move, e.g. 5+3
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub bonus: Duration,
+    pub kind: Bonus,
+}
+
+impl TimeControl {
+    // Read a time control as minutes and bonus seconds, "5+3" for an increment
+    // or "5d3" for a delay, a lone "10" has no bonus
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim().to_lowercase();
+        let (base, bonus, kind) = match text.split_once(['+', 'd']) {
+            Some((base, bonus)) => {
+                let kind = if text.contains('d') {
+                    Bonus::Delay
+                } else {
+                    Bonus::Increment
+                };
+                (base, bonus, kind)
+            }
+            None => (text.as_str(), "0", Bonus::Increment),
+        };
+
+        // "inf" and numbers too large for a Duration aren't time controls either
+        let minutes = base.trim().parse::<f64>().ok().filter(|&m| m > 0.0);
+        let base = minutes.and_then(|m| Duration::try_from_secs_f64(m * 60.0).ok());
+        let seconds = bonus.trim().parse::<f64>().ok();
+        let bonus = seconds.and_then(|s| Duration::try_from_secs_f64(s).ok());
+        match (base, bonus) {
+            (Some(base), Some(bonus)) => Ok(TimeControl { base, bonus, kind }),
+            _ => Err(format!(
+                "'{}' is not a time control, expected e.g. 5+3 or 5d3",
+                text
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let separator = match self.kind {
+            Bonus::Increment => "+",
+            Bonus::Delay => "d",
+        };
+        write!(
+            f,
+            "{}{}{}",
+            self.base.as_secs_f64() / 60.0,
+            separator,
+            self.bonus.as_secs_f64()
+        )
+    }
+}
+
+// The clocks of both players
+pub struct Clock {
+    control: TimeControl,
+    // Time left for White and Black when their clock was last stopped
+    remaining: [Duration; 2],
+    // The side whose clock is running and since when
+    running: Option<(bool, Instant)>,
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Self {
+        Clock {
+            control,
+            remaining: [control.base; 2],
+            running: None,
+        }
+    }
+
+    // Start the clock of the side to move
+    pub fn start(&mut self, is_white: bool) {
+        self.running = Some((is_white, Instant::now()));
+    }
+
+    // Stop the running clock after its side moved and add the bonus,
+    // returns false if the flag fell before the move was made
+    pub fn stop(&mut self) -> bool {
+        let (is_white, since) = match self.running.take() {
+            Some(running) => running,
+            None => return true,
+        };
+        let used = since.elapsed();
+        let remaining = &mut self.remaining[side(is_white)];
+
+        if used >= *remaining {
+            *remaining = Duration::ZERO;
+            return false;
+        }
+        *remaining -= used;
+        *remaining += match self.control.kind {
+            Bonus::Increment => self.control.bonus,
+            Bonus::Delay => used.min(self.control.bonus),
+        };
+        true
+    }
+
+    // Run f without it counting for the running clock, like while the opponent considers
+    // a draw offer
+    pub fn paused<T>(&mut self, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        if let Some((_, since)) = &mut self.running {
+            *since += start.elapsed();
+        }
+        result
+    }
+
+    // Time left for a side, counting down while its clock runs
+    pub fn remaining(&self, is_white: bool) -> Duration {
+        let remaining = self.remaining[side(is_white)];
+        match self.running {
+            Some((running, since)) if running == is_white => {
+                remaining.saturating_sub(since.elapsed())
+            }
+            _ => remaining,
+        }
+    }
+
+    // When the flag of the running side falls
+    pub fn deadline(&self) -> Option<Instant> {
+        self.running
+            .map(|(is_white, since)| since + self.remaining[side(is_white)])
+    }
+
+    // Time Magnus gives itself for a move: a share of what is left plus most of the bonus,
+    // never more than half of its time
+    pub fn budget(&self, is_white: bool) -> Duration {
+        let remaining = self.remaining(is_white);
+        (remaining / 30 + self.control.bonus * 3 / 4).min(remaining / 2)
+    }
+
+    // Both clocks, like "White 4:32 | Black 5:00"
+    pub fn display(&self) -> String {
+        format!(
+            "White {} | Black {}",
+            format_time(self.remaining(true)),
+            format_time(self.remaining(false))
+        )
+    }
+}
+
+fn side(is_white: bool) -> usize {
+    if is_white {
+        0
+    } else {
+        1
+    }
+}
+
+// Minutes and seconds, with tenths in the last ten seconds
+fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if seconds < 10 {
+        format!("0:{:04.1}", time.as_secs_f64())
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+// Whether a side has enough material left to mate, when it doesn't the game is drawn
+// instead of lost if the opponent's flag falls
+pub fn can_mate(board: &Board, is_white: bool) -> bool {
+    let colour = if is_white {
+        Colour::White
+    } else {
+        Colour::Black
+    };
+
+    let mut minor_pieces = 0;
+    for tile in board.tiles.iter().flatten() {
+        if tile.piece.colour != colour {
+            continue;
+        }
+        match tile.piece.piece_type {
+            Type::Pawn(_) | Type::Rook(_) | Type::Queen => return true,
+            Type::Knight | Type::Bishop => minor_pieces += 1,
+            _ => {}
+        }
+    }
+    minor_pieces >= 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control(text: &str) -> TimeControl {
+        TimeControl::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parses_increment_delay_and_no_bonus() {
+        let blitz = control("5+3");
+        assert_eq!(blitz.base, Duration::from_secs(300));
+        assert_eq!(blitz.bonus, Duration::from_secs(3));
+        assert_eq!(blitz.kind, Bonus::Increment);
+
+        let delay = control(" 1.5D2 ");
+        assert_eq!(delay.base, Duration::from_secs(90));
+        assert_eq!(delay.bonus, Duration::from_secs(2));
+        assert_eq!(delay.kind, Bonus::Delay);
+
+        let rapid = control("10");
+        assert_eq!(rapid.base, Duration::from_secs(600));
+        assert_eq!(rapid.bonus, Duration::ZERO);
+    }
+
+    #[test]
+    fn rejects_what_is_not_a_time_control() {
+        for text in [
+            "", "0", "-5", "5+-1", "five", "5+x", "inf", "5+inf", "nan", "1e300",
+        ] {
+            assert!(TimeControl::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn displays_what_it_parses() {
+        for text in ["5+3", "1.5d2", "10+0"] {
+            assert_eq!(control(text).to_string(), text);
+        }
+    }
+
+    #[test]
+    fn adds_the_increment_after_a_move() {
+        let mut clock = Clock::new(control("1+2"));
+        clock.start(true);
+        assert!(clock.stop());
+        let white = clock.remaining(true);
+        assert!(white > Duration::from_secs(61) && white <= Duration::from_secs(62));
+        assert_eq!(clock.remaining(false), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn gives_back_at_most_the_time_used_with_a_delay() {
+        let mut clock = Clock::new(control("1d2"));
+        clock.start(false);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(clock.stop());
+        assert_eq!(clock.remaining(false), Duration::from_secs(60));
+    }
+
+    #[test]
+    fn flag_falls_when_the_time_is_used_up() {
+        let mut clock = Clock::new(control("0.0001+5"));
+        clock.start(true);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(clock.remaining(true), Duration::ZERO);
+        assert!(!clock.stop());
+        assert_eq!(clock.remaining(true), Duration::ZERO);
+    }
+
+    #[test]
+    fn paused_time_is_not_used() {
+        let mut clock = Clock::new(control("0.001"));
+        clock.start(true);
+        clock.paused(|| std::thread::sleep(Duration::from_millis(100)));
+        assert!(clock.remaining(true) > Duration::from_millis(30));
+        assert!(clock.stop());
+    }
+
+    #[test]
+    fn budget_is_never_more_than_half_the_time_left() {
+        let clock = Clock::new(control("1+60"));
+        assert_eq!(clock.budget(true), Duration::from_secs(30));
+        let clock = Clock::new(control("5+3"));
+        assert_eq!(
+            clock.budget(true),
+            Duration::from_secs(10) + Duration::from_millis(2250)
+        );
+    }
+}
@@ -0,0 +1,480 @@
+use crate::*;
+use evaluation::MATE_SCORE;
+use std::sync::{Once, OnceLock};
+
+// Retrograde solved tables for king and queen, king and rook, and king and pawn against a lone
+// king, giving the distance to mate in plies for every position
+// Positions are stored with the strong side as White, so a pawn always moves towards row 0,
+// and tiles are numbered row * 8 + col like on the board
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Ending {
+    Queen,
+    Rook,
+    Pawn,
+}
+
+const STRONG: usize = 0;
+const WEAK: usize = 1;
+const TABLE_SIZE: usize = 2 * 64 * 64 * 64;
+
+// Not mated in any number of moves, which is a draw once solving is finished
+const DRAW: u8 = 255;
+const ILLEGAL: u8 = 254;
+// Move counter of a position the weak side can already draw, never counted down to 0
+const DRAWN_COUNTER: u8 = u8::MAX;
+
+static TABLES: OnceLock<[Vec<u8>; 3]> = OnceLock::new();
+static SOLVING: Once = Once::new();
+
+// Start solving the endings in the background, searches don't probe them until they're done
+// so they never wait for the solving, which takes seconds
+pub fn init() {
+    SOLVING.call_once(|| {
+        std::thread::spawn(tables);
+    });
+}
+
+// Plies to mate for the side to move: odd for a win of the strong side to move,
+// even for a loss of the weak side to move, DRAW when it's a draw
+fn tables() -> &'static [Vec<u8>; 3] {
+    TABLES.get_or_init(|| {
+        // Pawn promotions lead into the queen ending, so it is solved first
+        let queen = solve(Ending::Queen, None);
+        let rook = solve(Ending::Rook, None);
+        let pawn = solve(Ending::Pawn, Some(&queen));
+        [queen, rook, pawn]
+    })
+}
+
+fn index(to_move: usize, strong_king: usize, piece: usize, weak_king: usize) -> usize {
+    ((to_move * 64 + strong_king) * 64 + piece) * 64 + weak_king
+}
+
+fn adjacent(a: usize, b: usize) -> bool {
+    a != b && (a / 8).abs_diff(b / 8) <= 1 && (a % 8).abs_diff(b % 8) <= 1
+}
+
+// Tiles around a tile
+fn neighbours(tile: usize) -> impl Iterator<Item = usize> {
+    (0..64).filter(move |&other| adjacent(tile, other))
+}
+
+fn directions(ending: Ending) -> &'static [(i32, i32)] {
+    match ending {
+        Ending::Queen => &[
+            (-1, -1),
+            (-1, 0),
+            (-1, 1),
+            (0, -1),
+            (0, 1),
+            (1, -1),
+            (1, 0),
+            (1, 1),
+        ],
+        Ending::Rook => &[(-1, 0), (0, -1), (0, 1), (1, 0)],
+        Ending::Pawn => &[],
+    }
+}
+
+// Tiles a queen or rook slides to from a tile, stopping in front of any of the blockers
+// Sliding is symmetric, so this also gives the tiles it could have come from
+fn slides(ending: Ending, from: usize, blockers: [usize; 2]) -> Vec<usize> {
+    let mut tiles = Vec::new();
+
+    for &(dr, dc) in directions(ending) {
+        let (mut row, mut col) = ((from / 8) as i32, (from % 8) as i32);
+        loop {
+            row += dr;
+            col += dc;
+            if !(0..8).contains(&row) || !(0..8).contains(&col) {
+                break;
+            }
+
+            let tile = (row * 8 + col) as usize;
+            if blockers.contains(&tile) {
+                break;
+            }
+            tiles.push(tile);
+        }
+    }
+
+    tiles
+}
+
+// Whether the strong piece attacks a tile, the strong king being the only piece in the way
+fn piece_attacks(ending: Ending, piece: usize, target: usize, strong_king: usize) -> bool {
+    if piece == target {
+        return false;
+    }
+
+    match ending {
+        Ending::Pawn => piece / 8 == target / 8 + 1 && (piece % 8).abs_diff(target % 8) == 1,
+        _ => slides(ending, piece, [strong_king, 64]).contains(&target),
+    }
+}
+
+fn is_legal(
+    ending: Ending,
+    to_move: usize,
+    strong_king: usize,
+    piece: usize,
+    weak_king: usize,
+) -> bool {
+    if strong_king == piece || strong_king == weak_king || piece == weak_king {
+        return false;
+    }
+    if adjacent(strong_king, weak_king) {
+        return false;
+    }
+    // Pawns never stand on their own back rank or the promotion rank
+    if ending == Ending::Pawn && (piece / 8 == 0 || piece / 8 == 7) {
+        return false;
+    }
+    // The weak side can't have left its king in check
+    !(to_move == STRONG && piece_attacks(ending, piece, weak_king, strong_king))
+}
+
+// Solve an ending backwards from the mates: a position with the weak side to move is lost once
+// all of its moves lead to lost positions, and one with the strong side to move is won once any
+// move leads to a position the weak side has lost
+fn solve(ending: Ending, queen_table: Option<&Vec<u8>>) -> Vec<u8> {
+    let mut dtm = vec![DRAW; TABLE_SIZE];
+    let mut counters = vec![0u8; TABLE_SIZE];
+    // Positions waiting to be resolved, by plies to mate
+    let mut buckets: Vec<Vec<usize>> = vec![Vec::new()];
+
+    for strong_king in 0..64 {
+        for piece in 0..64 {
+            for weak_king in 0..64 {
+                for to_move in [STRONG, WEAK] {
+                    let i = index(to_move, strong_king, piece, weak_king);
+                    if !is_legal(ending, to_move, strong_king, piece, weak_king) {
+                        dtm[i] = ILLEGAL;
+                        continue;
+                    }
+                    if to_move == STRONG {
+                        continue;
+                    }
+
+                    let mut moves = 0;
+                    let mut can_draw = false;
+                    for tile in neighbours(weak_king) {
+                        if adjacent(tile, strong_king) {
+                            continue;
+                        }
+                        if tile == piece {
+                            // Taking the undefended piece leaves a bare king each
+                            can_draw = true;
+                        } else if !piece_attacks(ending, piece, tile, strong_king) {
+                            moves += 1;
+                        }
+                    }
+
+                    let in_check = piece_attacks(ending, piece, weak_king, strong_king);
+                    if can_draw || (moves == 0 && !in_check) {
+                        counters[i] = DRAWN_COUNTER;
+                    } else if moves == 0 {
+                        buckets[0].push(i);
+                    } else {
+                        counters[i] = moves;
+                    }
+                }
+            }
+        }
+    }
+
+    // Promoting leads to a queen ending that is already solved
+    if let Some(queen_table) = queen_table {
+        for strong_king in 0..64 {
+            for weak_king in 0..64 {
+                for piece in 8..16 {
+                    let i = index(STRONG, strong_king, piece, weak_king);
+                    let promotion = piece - 8;
+                    if dtm[i] == ILLEGAL || promotion == strong_king || promotion == weak_king {
+                        continue;
+                    }
+
+                    let after = queen_table[index(WEAK, strong_king, promotion, weak_king)];
+                    if after != DRAW && after != ILLEGAL {
+                        push(&mut buckets, after as usize + 1, i);
+                    }
+                }
+            }
+        }
+    }
+
+    let mut ply = 0;
+    while ply < buckets.len() {
+        let positions = std::mem::take(&mut buckets[ply]);
+        for i in positions {
+            if dtm[i] != DRAW {
+                continue;
+            }
+            dtm[i] = ply as u8;
+
+            let weak_king = i % 64;
+            let piece = i / 64 % 64;
+            let strong_king = i / 64 / 64 % 64;
+
+            if i / (64 * 64 * 64) == WEAK {
+                for before in strong_unmoves(ending, strong_king, piece, weak_king) {
+                    if dtm[before] == DRAW {
+                        push(&mut buckets, ply + 1, before);
+                    }
+                }
+            } else {
+                for tile in neighbours(weak_king) {
+                    if tile == strong_king || tile == piece || adjacent(tile, strong_king) {
+                        continue;
+                    }
+
+                    let before = index(WEAK, strong_king, piece, tile);
+                    if dtm[before] != DRAW || counters[before] == DRAWN_COUNTER {
+                        continue;
+                    }
+                    counters[before] -= 1;
+                    if counters[before] == 0 {
+                        push(&mut buckets, ply + 1, before);
+                    }
+                }
+            }
+        }
+        ply += 1;
+    }
+
+    dtm
+}
+
+fn push(buckets: &mut Vec<Vec<usize>>, ply: usize, i: usize) {
+    if buckets.len() <= ply {
+        buckets.resize(ply + 1, Vec::new());
+    }
+    buckets[ply].push(i);
+}
+
+// Positions with the strong side to move that lead to this one, weak side to move, by a move
+// of the strong king or piece
+fn strong_unmoves(
+    ending: Ending,
+    strong_king: usize,
+    piece: usize,
+    weak_king: usize,
+) -> Vec<usize> {
+    let mut before = Vec::new();
+
+    for tile in neighbours(strong_king) {
+        if tile != piece && tile != weak_king {
+            before.push((tile, piece));
+        }
+    }
+
+    match ending {
+        Ending::Pawn => {
+            let (row, col) = (piece / 8, piece % 8);
+            let empty = |tile: usize| tile != strong_king && tile != weak_king;
+            if row < 6 && empty(piece + 8) {
+                before.push((strong_king, piece + 8));
+                // A double step from the starting rank
+                if row == 4 && empty(6 * 8 + col) {
+                    before.push((strong_king, 6 * 8 + col));
+                }
+            }
+        }
+        _ => {
+            for tile in slides(ending, piece, [strong_king, weak_king]) {
+                before.push((strong_king, tile));
+            }
+        }
+    }
+
+    before
+        .into_iter()
+        .filter(|&(king, piece)| is_legal(ending, STRONG, king, piece, weak_king))
+        .map(|(king, piece)| index(STRONG, king, piece, weak_king))
+        .collect()
+}
+
+// Score of the position for the side to move if it is one of the solved endings or bare kings,
+// with mates scored like the search does from the root, None while they're still being solved
+pub fn probe(board: &Board, is_white: bool) -> Option<i32> {
+    let (ending, strong_is_white, strong_king, piece, weak_king) = classify(board)?;
+    let ending = match ending {
+        Some(ending) => ending,
+        None => return Some(0),
+    };
+    let tables = TABLES.get()?;
+
+    let to_move = if is_white == strong_is_white {
+        STRONG
+    } else {
+        WEAK
+    };
+    match tables[ending as usize][index(to_move, strong_king, piece, weak_king)] {
+        DRAW | ILLEGAL => Some(0),
+        plies if to_move == STRONG => Some(MATE_SCORE - plies as i32),
+        plies => Some(-MATE_SCORE + plies as i32),
+    }
+}
+
+// Plies to mate with best play, if the strong side can force it, waits for the solving
+pub fn plies_to_mate(board: &Board, is_white: bool) -> Option<i32> {
+    tables();
+    probe(board, is_white)
+        .filter(|score| score.abs() > MATE_SCORE - 1000)
+        .map(|score| MATE_SCORE - score.abs())
+}
+
+// The ending on the board, which side is the strong one and the tiles of the strong king, its
+// piece and the weak king with the strong side turned into White
+// The ending is None when only the kings are left
+fn classify(board: &Board) -> Option<(Option<Ending>, bool, usize, usize, usize)> {
+    let mut extra = None;
+
+    for (row, tiles) in board.tiles.iter().enumerate() {
+        for (col, tile) in tiles.iter().enumerate() {
+            let ending = match tile.piece.piece_type {
+                Type::Empty | Type::King(_) => continue,
+                Type::Queen => Ending::Queen,
+                Type::Rook(_) => Ending::Rook,
+                Type::Pawn(_) => Ending::Pawn,
+                _ => return None,
+            };
+            // A second piece besides the kings, so none of the solved endings and no need
+            // to look at the rest of the board
+            if extra.is_some() {
+                return None;
+            }
+            extra = Some((ending, tile.piece.colour == Colour::White, (row, col)));
+        }
+    }
+
+    let (ending, strong_is_white, piece) = match extra {
+        Some((ending, strong_is_white, piece)) => (Some(ending), strong_is_white, piece),
+        None => (None, true, board.kingpos_w),
+    };
+    let (strong_king, weak_king) = if strong_is_white {
+        (board.kingpos_w, board.kingpos_b)
+    } else {
+        (board.kingpos_b, board.kingpos_w)
+    };
+    // Flip the board for Black, so its pawn moves towards row 0 as well
+    let tile = |(row, col): (usize, usize)| {
+        if strong_is_white {
+            row * 8 + col
+        } else {
+            (7 - row) * 8 + col
+        }
+    };
+
+    Some((
+        ending,
+        strong_is_white,
+        tile(strong_king),
+        tile(piece),
+        tile(weak_king),
+    ))
+}
+
+// A random position of the ending with White to move and win, taking at least min_plies to mate
+pub fn random_position(ending: Ending, min_plies: u8) -> Board {
+    let table = &tables()[ending as usize];
+
+    loop {
+        let (strong_king, piece, weak_king) = (
+            fastrand::usize(..64),
+            fastrand::usize(..64),
+            fastrand::usize(..64),
+        );
+        let plies = table[index(STRONG, strong_king, piece, weak_king)];
+        if plies == DRAW || plies == ILLEGAL || plies < min_plies {
+            continue;
+        }
+
+        let mut board = Board::new();
+        for tile in board.tiles.iter_mut().flatten() {
+            tile.piece = Piece {
+                piece_type: Type::Empty,
+                colour: Colour::White,
+            };
+        }
+
+        let at = |tile: usize| (tile / 8, tile % 8);
+        let piece_type = match ending {
+            Ending::Queen => Type::Queen,
+            Ending::Rook => Type::Rook(true),
+            // Only a pawn on its starting rank may still advance two tiles
+            Ending::Pawn => Type::Pawn(piece / 8 != 6),
+        };
+        let pieces = [
+            (strong_king, Type::King(true), Colour::White),
+            (piece, piece_type, Colour::White),
+            (weak_king, Type::King(true), Colour::Black),
+        ];
+        for (tile, piece_type, colour) in pieces {
+            let (row, col) = at(tile);
+            board.tiles[row][col].piece = Piece { piece_type, colour };
+        }
+        board.kingpos_w = at(strong_king);
+        board.kingpos_b = at(weak_king);
+
+        return board;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plies(fen: &str) -> Option<i32> {
+        let (board, is_white) = fen::parse_fen(fen).unwrap();
+        plies_to_mate(&board, is_white)
+    }
+
+    // Longest mate in plies of an ending with the strong side to move
+    fn longest(ending: Ending) -> u8 {
+        let table = &tables()[ending as usize];
+        (0..64 * 64 * 64)
+            .map(|i| table[index(STRONG, 0, 0, 0) + i])
+            .filter(|&plies| plies != DRAW && plies != ILLEGAL)
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn longest_mates_match_the_known_ones() {
+        // Mate in 10 with the queen and in 16 with the rook
+        assert_eq!(longest(Ending::Queen), 19);
+        assert_eq!(longest(Ending::Rook), 31);
+    }
+
+    #[test]
+    fn counts_plies_to_mate_for_either_side_to_move() {
+        assert_eq!(plies("k7/7Q/1K6/8/8/8/8/8 w - - 0 1"), Some(1));
+        assert_eq!(plies("k7/Q7/1K6/8/8/8/8/8 b - - 0 1"), Some(0));
+        assert_eq!(plies("8/8/8/8/8/1k6/7q/K7 b - - 0 1"), Some(1));
+        // Ka8 stalemated
+        assert_eq!(plies("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"), None);
+    }
+
+    #[test]
+    fn knows_won_and_drawn_pawn_endings() {
+        // The king on the sixth in front of its pawn wins, with either side to move
+        assert!(plies("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1").is_some());
+        assert!(plies("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").is_some());
+        // One rank lower it's a draw when the defender has the opposition
+        assert_eq!(plies("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"), None);
+        // A rook pawn is a draw once the defending king reaches the corner
+        assert_eq!(plies("k7/8/8/P7/8/8/8/7K w - - 0 1"), None);
+        // Black's pawn promotes towards rank 1
+        assert!(plies("8/8/8/8/4p3/4k3/8/4K3 b - - 0 1").is_some());
+    }
+
+    #[test]
+    fn only_kings_and_one_piece_are_solved() {
+        let (board, _) = fen::parse_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(probe(&board, true), Some(0));
+        let (board, _) = fen::parse_fen("4k3/8/8/8/8/8/8/R3K2R w - - 0 1").unwrap();
+        assert_eq!(probe(&board, true), None);
+    }
+}
@@ -1,6 +1,26 @@
 use crate::*;
-use evaluation::evaluate_board;
-use moves::move_piece;
+use evaluation::{evaluate_board, MATE_SCORE, MAX_EVAL};
+use moves::{is_in_check, move_piece};
+use nnue::{Accumulator, Network};
+use params::EvalParams;
+use skill::Skill;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use tt::{Bound, TranspositionTable, DEFAULT_TT_SIZE_MB};
+
+// Bound used for the alpha-beta window, larger than any reachable score
+pub const INFINITY: i32 = MATE_SCORE + 1;
+
+pub struct SearchResult {
+    pub best_move: Move,
+    // Score relative to the side that was searched for
+    pub score: i32,
+    // The principal variation, starting with best_move
+    pub pv: Vec<Move>,
+    // The best lines with their scores, best first, as many as asked for with MultiPV
+    pub lines: Vec<(i32, Vec<Move>)>,
+}
 
 // get all the possible moves for a side, and return a vector with tuples of ((usize, usize), (usize, usize)) representing the from and to positions of the move
 pub fn get_all_moves(board: Board, is_white: bool) -> Vec<((usize, usize), Vec<(usize, usize)>)> {
@@ -31,115 +51,714 @@ pub fn get_all_moves(board: Board, is_white: bool) -> Vec<((usize, usize), Vec<(
     return moves;
 }
 
-pub fn minimax(board: Board, depth: i32, mut alpha: i32, mut beta: i32, is_white: bool) -> i32 {
-    // White is maximizer
-    // Black is minimizer
+// Toggles for the pruning techniques used by the search, so their strength impact can be measured
+#[derive(Clone, Copy, Debug)]
+pub struct SearchOptions {
+    // Give the opponent a free move and prune if the position is still good enough
+    pub null_move: bool,
+    // Search quiet moves late in the move order at a reduced depth
+    pub late_move_reductions: bool,
+    // Search moves after the first one with a null window (principal variation search)
+    pub pvs: bool,
+    // Start each iteration with a narrow window around the previous score
+    pub aspiration_windows: bool,
+}
 
-    if depth == 0 {
-        return evaluate_board(board);
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            null_move: true,
+            late_move_reductions: true,
+            pvs: true,
+            aspiration_windows: true,
+        }
     }
+}
 
-    // if maximizing player
-    if is_white {
-        let mut max_eval = std::i32::MIN;
-        let moves = get_all_moves(board, true);
+// Depth reduction of the null move search
+const NULL_MOVE_REDUCTION: i32 = 2;
+// Number of moves searched at full depth before late move reductions kick in
+const LMR_FULL_DEPTH_MOVES: usize = 3;
+// Minimum remaining depth for null move pruning and late move reductions
+const REDUCTION_LIMIT: i32 = 3;
+// Initial half-width of the aspiration window in centipawns
+const ASPIRATION_WINDOW: i32 = 50;
 
-        for (from, to) in moves {
-            for to_pos in to {
-                // Do the move on a cloned board
-                let mut new_board = board.clone();
-                match move_piece(&mut new_board, from, to_pos, true) {
-                    Ok(_) => (),
-                    Err(_) => continue,
+// Magnus' search, holding its options and the transposition table that is kept between moves
+#[derive(Clone)]
+pub struct Engine {
+    pub options: SearchOptions,
+    // Number of threads searching in parallel (Lazy SMP)
+    pub threads: usize,
+    // Weights of the evaluation
+    pub params: Arc<EvalParams>,
+    // Evaluate with this neural network instead of the handcrafted evaluation
+    pub network: Option<Arc<Network>>,
+    // Play weaker than possible, None for full strength
+    pub skill: Option<Skill>,
+    // Number of best moves to find exact scores and lines for (MultiPV)
+    pub multi_pv: usize,
+    tt: Arc<TranspositionTable>,
+}
+
+impl Engine {
+    pub fn new(threads: usize, params: Arc<EvalParams>) -> Self {
+        endgame::init();
+        Engine {
+            options: SearchOptions::default(),
+            threads: threads.max(1),
+            params,
+            network: None,
+            skill: None,
+            multi_pv: 1,
+            tt: Arc::new(TranspositionTable::new(DEFAULT_TT_SIZE_MB)),
+        }
+    }
+
+    // Start searching on a worker thread, the returned handle can stop the search early
+    pub fn start_search(&self, board: Board, depth: i32, is_white: bool) -> SearchHandle {
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let engine = self.clone();
+
+        let thread =
+            std::thread::spawn(move || lazy_smp(&engine, board, depth, is_white, &thread_stop));
+
+        SearchHandle { stop, thread }
+    }
+}
+
+// A search running in the background
+pub struct SearchHandle {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<SearchResult>,
+}
+
+impl SearchHandle {
+    // Ask the search to return the best move it has found so far
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.thread.is_finished()
+    }
+
+    // Block until the search is done and take its result
+    pub fn wait(self) -> SearchResult {
+        self.thread.join().unwrap()
+    }
+}
+
+// Helper threads search the same root and share their findings through the transposition table,
+// the result always comes from the main thread
+fn lazy_smp(
+    engine: &Engine,
+    board: Board,
+    depth: i32,
+    is_white: bool,
+    stop: &AtomicBool,
+) -> SearchResult {
+    // A weakened search counts its nodes, which only works on a single thread
+    let threads = if engine.skill.is_some_and(|skill| !skill.is_full_strength()) {
+        1
+    } else {
+        engine.threads
+    };
+
+    std::thread::scope(|scope| {
+        for id in 1..threads {
+            let mut helper = Searcher::new(engine, stop);
+            scope.spawn(move || {
+                // Half of the helpers search one ply deeper so the threads don't all do the same work
+                helper.iterate(board, depth + (id % 2) as i32, is_white);
+            });
+        }
+
+        let mut searcher = Searcher::new(engine, stop);
+        let result = searcher.iterate(board, depth, is_white);
+        stop.store(true, Ordering::Relaxed);
+        result
+    })
+}
+
+struct Searcher<'a> {
+    options: SearchOptions,
+    params: &'a EvalParams,
+    network: Option<&'a Network>,
+    // Network accumulators of the positions on the current line, by ply
+    accumulators: Vec<Accumulator>,
+    tt: &'a TranspositionTable,
+    // Only set when playing below full strength
+    skill: Option<Skill>,
+    multi_pv: usize,
+    // Positions visited so far
+    nodes: u64,
+    // Set once the search should be abandoned
+    stop: &'a AtomicBool,
+    // Principal variation of the previous iteration, used for move ordering
+    prev_pv: Vec<Move>,
+    // The first iteration always finishes, so there is a move to return
+    can_stop: bool,
+}
+
+impl<'a> Searcher<'a> {
+    fn new(engine: &'a Engine, stop: &'a AtomicBool) -> Self {
+        Searcher {
+            options: engine.options,
+            params: &engine.params,
+            network: engine.network.as_deref(),
+            accumulators: Vec::new(),
+            tt: &engine.tt,
+            skill: engine.skill.filter(|skill| !skill.is_full_strength()),
+            multi_pv: engine.multi_pv.max(1),
+            nodes: 0,
+            stop,
+            prev_pv: Vec::new(),
+            can_stop: false,
+        }
+    }
+
+    fn stopped(&self) -> bool {
+        let out_of_nodes = self
+            .skill
+            .and_then(|skill| skill.node_limit())
+            .is_some_and(|limit| self.nodes >= limit);
+        self.can_stop && (out_of_nodes || self.stop.load(Ordering::Relaxed))
+    }
+
+    // Negamax search with alpha-beta pruning, scores are relative to the side to move
+    // The best line found from this position is written into pv
+    #[allow(clippy::too_many_arguments)]
+    fn negamax(
+        &mut self,
+        board: Board,
+        depth: i32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+        is_white: bool,
+        pv: &mut Vec<Move>,
+        allow_null: bool,
+    ) -> i32 {
+        pv.clear();
+        self.nodes += 1;
+
+        if self.stopped() {
+            return 0;
+        }
+
+        // Solved endings are looked up instead of searched
+        if ply > 0 {
+            if let Some(score) = endgame::probe(&board, is_white) {
+                return if score > MATE_SCORE - 1000 {
+                    score - ply
+                } else if score < -MATE_SCORE + 1000 {
+                    score + ply
+                } else {
+                    score
+                };
+            }
+        }
+
+        if depth <= 0 {
+            let eval = self.evaluate(board, ply, is_white);
+
+            // Prefer the quickest mate and the slowest loss
+            if eval == MATE_SCORE {
+                return MATE_SCORE - ply;
+            } else if eval == -MATE_SCORE {
+                return -MATE_SCORE + ply;
+            }
+            return eval;
+        }
+
+        // Use a stored result for this position if it was searched deep enough,
+        // except on the principal variation where the full line is wanted
+        let hash = zobrist::hash_board(&board, is_white);
+        let tt_entry = self.tt.probe(hash, ply);
+        let is_pv_node = beta - alpha > 1;
+        if let Some(entry) = tt_entry {
+            if !is_pv_node && ply > 0 && entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return entry.score,
+                    Bound::Lower if entry.score >= beta => return entry.score,
+                    Bound::Upper if entry.score <= alpha => return entry.score,
+                    _ => (),
                 }
+            }
+        }
+        let tt_move = tt_entry.and_then(|entry| entry.best_move);
 
-                // Recursively call minimax on the new board
-                let eval = minimax(new_board, depth - 1, alpha, beta, false);
-                max_eval = std::cmp::max(max_eval, eval);
+        let in_check = is_in_check(board, is_white);
+        let mut child_pv = Vec::new();
 
-                // Alpha beta pruning
-                alpha = std::cmp::max(alpha, eval);
-                if beta <= alpha {
-                    break;
+        // Null move pruning, skipped in check, right after another null move and when the
+        // side only has pawns left, since those positions are prone to zugzwang
+        if self.options.null_move
+            && allow_null
+            && ply > 0
+            && depth >= REDUCTION_LIMIT
+            && !in_check
+            && beta.abs() < MATE_SCORE - 1000
+            && has_non_pawn_material(board, is_white)
+        {
+            self.push_accumulator(ply, &board, &board);
+            let eval = -self.negamax(
+                board,
+                depth - 1 - NULL_MOVE_REDUCTION,
+                ply + 1,
+                -beta,
+                -beta + 1,
+                !is_white,
+                &mut child_pv,
+                false,
+            );
+
+            if eval >= beta {
+                pv.clear();
+                return beta;
+            }
+        }
+
+        let original_alpha = alpha;
+        let mut best_eval = -INFINITY;
+        let mut best_move = None;
+        let mut legal_moves = 0;
+
+        for (from, to_pos) in self.ordered_moves(board, is_white, ply, tt_move) {
+            // Do the move on a copy of the board
+            let mut new_board = board;
+            if move_piece(&mut new_board, from, to_pos, is_white).is_err() {
+                continue;
+            }
+            legal_moves += 1;
+            self.push_accumulator(ply, &board, &new_board);
+
+            let eval;
+            if legal_moves == 1 {
+                eval = -self.negamax(
+                    new_board,
+                    depth - 1,
+                    ply + 1,
+                    -beta,
+                    -alpha,
+                    !is_white,
+                    &mut child_pv,
+                    true,
+                );
+            } else {
+                // Late move reductions for quiet moves that don't give check
+                let mut reduction = 0;
+                if self.options.late_move_reductions
+                    && legal_moves > LMR_FULL_DEPTH_MOVES
+                    && depth >= REDUCTION_LIMIT
+                    && !in_check
+                    && board.tiles[to_pos.0][to_pos.1].piece.piece_type == Type::Empty
+                    && !is_promotion(board, (from, to_pos))
+                    && !is_in_check(new_board, !is_white)
+                {
+                    reduction = 1;
                 }
+
+                // Principal variation search assumes the first move was best and only
+                // proves the others are worse with a null window
+                let window_beta = if self.options.pvs { alpha + 1 } else { beta };
+
+                let mut reduced_eval = -self.negamax(
+                    new_board,
+                    depth - 1 - reduction,
+                    ply + 1,
+                    -window_beta,
+                    -alpha,
+                    !is_white,
+                    &mut child_pv,
+                    true,
+                );
+
+                // The reduced search beat alpha, verify it at full depth
+                if reduction > 0 && reduced_eval > alpha {
+                    reduced_eval = -self.negamax(
+                        new_board,
+                        depth - 1,
+                        ply + 1,
+                        -window_beta,
+                        -alpha,
+                        !is_white,
+                        &mut child_pv,
+                        true,
+                    );
+                }
+
+                // The null window search failed high, search again with the full window
+                if self.options.pvs && reduced_eval > alpha && reduced_eval < beta {
+                    reduced_eval = -self.negamax(
+                        new_board,
+                        depth - 1,
+                        ply + 1,
+                        -beta,
+                        -alpha,
+                        !is_white,
+                        &mut child_pv,
+                        true,
+                    );
+                }
+
+                eval = reduced_eval;
+            }
+
+            if self.stopped() {
+                return 0;
+            }
+
+            if eval > best_eval {
+                best_eval = eval;
+                best_move = Some((from, to_pos));
+            }
+
+            if eval > alpha {
+                alpha = eval;
+                pv.clear();
+                pv.push((from, to_pos));
+                pv.extend_from_slice(&child_pv);
+            }
+
+            // Alpha beta pruning
+            if alpha >= beta {
+                break;
             }
         }
-        return max_eval;
-    } else {
-        let mut min_eval = std::i32::MAX;
-        let moves = get_all_moves(board, false);
 
-        for (from, to) in moves {
-            for to_pos in to {
-                let mut new_board = board.clone();
-                match move_piece(&mut new_board, from, to_pos, false) {
-                    Ok(_) => (),
-                    Err(_) => continue,
+        // No legal moves means either checkmate or stalemate
+        if legal_moves == 0 {
+            if in_check {
+                return -MATE_SCORE + ply;
+            }
+            return 0;
+        }
+
+        let bound = if best_eval >= beta {
+            Bound::Lower
+        } else if best_eval > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(hash, ply, depth, best_eval, bound, best_move);
+
+        best_eval
+    }
+
+    // Static evaluation relative to the side to move
+    fn evaluate(&self, board: Board, ply: i32, is_white: bool) -> i32 {
+        let eval = match self.network {
+            None => {
+                let eval = evaluate_board(board, self.params);
+                if is_white {
+                    eval
+                } else {
+                    -eval
                 }
+            }
+            // The network knows nothing about mates
+            Some(network) => match check_for_mates(board) {
+                Some(Colour::White) if is_white => MATE_SCORE,
+                Some(Colour::Black) if !is_white => MATE_SCORE,
+                Some(_) => -MATE_SCORE,
+                None => network.evaluate(&self.accumulators[ply as usize], is_white),
+            },
+        };
 
-                let eval = minimax(new_board, depth - 1, alpha, beta, true);
-                min_eval = std::cmp::min(min_eval, eval);
+        // A weakened engine misjudges positions, but never mates, and its errors mustn't
+        // turn an evaluation into one
+        match self.skill {
+            Some(skill) if eval.abs() <= MAX_EVAL => {
+                let noise = skill.noise(zobrist::hash_board(&board, is_white));
+                (eval + noise).clamp(-MAX_EVAL, MAX_EVAL)
+            }
+            _ => eval,
+        }
+    }
 
-                beta = std::cmp::min(beta, eval);
-                if beta <= alpha {
+    // Set up the accumulator of the position after a move from the one before it
+    fn push_accumulator(&mut self, ply: i32, board: &Board, new_board: &Board) {
+        if let Some(network) = self.network {
+            let ply = ply as usize;
+            if self.accumulators.len() <= ply + 1 {
+                self.accumulators.push(self.accumulators[ply].clone());
+            }
+
+            let (parent, child) = self.accumulators.split_at_mut(ply + 1);
+            child[0].copy_from(&parent[ply]);
+            network.update(&mut child[0], board, new_board);
+        }
+    }
+
+    // Iteratively deepen up to the given depth, each iteration ordering its moves by the previous one
+    // If the search is stopped, the result of the last finished iteration is returned
+    fn iterate(&mut self, board: Board, depth: i32, is_white: bool) -> SearchResult {
+        let mut score: i32 = 0;
+        let mut pv = Vec::new();
+        if let Some(network) = self.network {
+            self.accumulators = vec![network.refresh(&board)];
+        }
+        // Several root moves get exact scores for MultiPV and for a weakened engine to choose from
+        let line_count = match self.skill {
+            Some(_) => self.multi_pv.max(skill::CANDIDATES),
+            None => self.multi_pv,
+        };
+        let mut lines = Vec::new();
+
+        'deepening: for current_depth in 1..=depth.max(1) {
+            if line_count > 1 {
+                let found = self.root_lines(board, current_depth, is_white, line_count);
+                if self.stopped() || found.is_empty() {
                     break;
                 }
+
+                (score, pv) = found[0].clone();
+                lines = found;
+                self.prev_pv = pv.clone();
+                self.can_stop = true;
+                continue;
+            }
+
+            let mut window = ASPIRATION_WINDOW;
+            let use_window = self.options.aspiration_windows
+                && current_depth > 1
+                && score.abs() < MATE_SCORE - 1000;
+            let (mut alpha, mut beta) = if use_window {
+                (score - window, score + window)
+            } else {
+                (-INFINITY, INFINITY)
+            };
+
+            loop {
+                let mut line = Vec::new();
+                let eval = self.negamax(
+                    board,
+                    current_depth,
+                    0,
+                    alpha,
+                    beta,
+                    is_white,
+                    &mut line,
+                    false,
+                );
+
+                if self.stopped() {
+                    break 'deepening;
+                }
+
+                // Widen the window and search again if the score fell outside of it
+                if eval <= alpha && alpha > -INFINITY {
+                    window *= 2;
+                    alpha = (eval - window).max(-INFINITY);
+                    continue;
+                }
+                if eval >= beta && beta < INFINITY {
+                    window *= 2;
+                    beta = (eval + window).min(INFINITY);
+                    continue;
+                }
+
+                score = eval;
+                if !line.is_empty() {
+                    pv = line;
+                }
+                break;
             }
+
+            self.prev_pv = pv.clone();
+            self.can_stop = true;
+        }
+
+        if lines.is_empty() {
+            lines.push((score, pv.clone()));
+        }
+        if let Some(skill) = self.skill {
+            let scores: Vec<i32> = lines.iter().map(|&(score, _)| score).collect();
+            (score, pv) = lines[skill.pick(&scores)].clone();
+        }
+
+        SearchResult {
+            best_move: pv.first().copied().unwrap_or(((0, 0), (0, 0))),
+            score,
+            pv,
+            lines,
         }
-        return min_eval;
     }
-}
 
-pub fn max_move(board: Board, depth: i32) -> ((usize, usize), (usize, usize)) {
-    // White is maximizer
+    // Find the best count root moves with exact scores and lines, best first (MultiPV)
+    // Once that many lines are known, other moves only have to be proven worse than the last one
+    fn root_lines(
+        &mut self,
+        board: Board,
+        depth: i32,
+        is_white: bool,
+        count: usize,
+    ) -> Vec<(i32, Vec<Move>)> {
+        let mut lines: Vec<(i32, Vec<Move>)> = Vec::new();
+        let mut child_pv = Vec::new();
 
-    let mut max_eval = std::i32::MIN;
-    let mut best_move = ((0, 0), (0, 0));
-    let moves = get_all_moves(board, true);
+        for move_ in self.ordered_moves(board, is_white, 0, None) {
+            let mut new_board = board;
+            if move_piece(&mut new_board, move_.0, move_.1, is_white).is_err() {
+                continue;
+            }
+            self.push_accumulator(0, &board, &new_board);
 
-    for (from, to) in moves {
-        for to_pos in to {
-            let mut new_board = board.clone();
-            match move_piece(&mut new_board, from, to_pos, true) {
-                Ok(_) => (),
-                Err(_) => continue,
+            if lines.len() == count {
+                let bound = lines[count - 1].0;
+                let eval = -self.negamax(
+                    new_board,
+                    depth - 1,
+                    1,
+                    -bound - 1,
+                    -bound,
+                    !is_white,
+                    &mut child_pv,
+                    true,
+                );
+                if eval <= bound && !self.stopped() {
+                    continue;
+                }
             }
 
-            let eval = minimax(new_board, depth - 1, std::i32::MIN, std::i32::MAX, false);
-            if eval > max_eval {
-                max_eval = eval;
-                best_move = (from, to_pos);
+            let eval = -self.negamax(
+                new_board,
+                depth - 1,
+                1,
+                -INFINITY,
+                INFINITY,
+                !is_white,
+                &mut child_pv,
+                true,
+            );
+            if self.stopped() {
+                return Vec::new();
             }
+
+            let mut line = vec![move_];
+            line.extend_from_slice(&child_pv);
+            let index = lines.partition_point(|&(score, _)| score >= eval);
+            lines.insert(index, (eval, line));
+            lines.truncate(count);
         }
+
+        lines
     }
 
-    println!("Best move: {:?} with score: {}", best_move, max_eval);
-    return best_move;
+    // Get all moves for a side, ordered so that the most promising are searched first: the
+    // transposition table move, the previous principal variation move, then captures of
+    // valuable pieces by cheap ones
+    fn ordered_moves(
+        &self,
+        board: Board,
+        is_white: bool,
+        ply: i32,
+        tt_move: Option<Move>,
+    ) -> Vec<Move> {
+        let pv_move = self.prev_pv.get(ply as usize).copied();
+        let mut moves: Vec<(i32, Move)> = Vec::new();
+
+        for (from, to) in get_all_moves(board, is_white) {
+            for to_pos in to {
+                let move_ = (from, to_pos);
+                let score = if Some(move_) == tt_move {
+                    i32::MAX
+                } else if Some(move_) == pv_move {
+                    i32::MAX - 1
+                } else {
+                    let victim = board.tiles[to_pos.0][to_pos.1].piece.piece_type;
+                    let attacker = board.tiles[from.0][from.1].piece.piece_type;
+                    if victim == Type::Empty {
+                        0
+                    } else {
+                        10 * piece_order_value(victim) - piece_order_value(attacker)
+                    }
+                };
+                moves.push((score, move_));
+            }
+        }
+
+        moves.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        moves.into_iter().map(|(_, move_)| move_).collect()
+    }
 }
 
-pub fn min_move(board: Board, depth: i32) -> ((usize, usize), (usize, usize)) {
-    // Black is minimizer
+// Rough piece values used only for ordering captures
+fn piece_order_value(piece: Type) -> i32 {
+    match piece {
+        Type::Pawn(_) => 1,
+        Type::Knight | Type::Bishop => 3,
+        Type::Rook(_) => 5,
+        Type::Queen => 9,
+        Type::King(_) => 10,
+        Type::Empty => 0,
+    }
+}
 
-    let mut min_eval = std::i32::MAX;
-    let mut best_move = ((0, 0), (0, 0));
-    let moves = get_all_moves(board, false);
+fn is_promotion(board: Board, move_: Move) -> bool {
+    let (from, to) = move_;
+    matches!(board.tiles[from.0][from.1].piece.piece_type, Type::Pawn(_))
+        && (to.0 == 0 || to.0 == 7)
+}
 
-    for (from, to) in moves {
-        for to_pos in to {
-            let mut new_board = board.clone();
-            match move_piece(&mut new_board, from, to_pos, false) {
-                Ok(_) => (),
-                Err(_) => continue,
-            }
+// Check if a side has anything besides pawns and its king
+fn has_non_pawn_material(board: Board, is_white: bool) -> bool {
+    let colour = if is_white {
+        Colour::White
+    } else {
+        Colour::Black
+    };
 
-            let eval = minimax(new_board, depth - 1, std::i32::MIN, std::i32::MAX, true);
-            if eval < min_eval {
-                min_eval = eval;
-                best_move = (from, to_pos);
-            }
+    board.tiles.iter().flatten().any(|tile| {
+        tile.piece.colour == colour
+            && matches!(
+                tile.piece.piece_type,
+                Type::Knight | Type::Bishop | Type::Rook(_) | Type::Queen
+            )
+    })
+}
+
+// Format a score from White's point of view, e.g. "+0.45" or "#-3"
+pub fn format_score(score: i32) -> String {
+    if score.abs() > MATE_SCORE - 1000 {
+        let moves = (MATE_SCORE - score.abs() + 1) / 2;
+        if score > 0 {
+            format!("#{}", moves)
+        } else {
+            format!("#-{}", moves)
         }
+    } else {
+        format!("{:+.2}", score as f64 / 100.0)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    println!("Best move: {:?} with score: {}", best_move, min_eval);
-    return best_move;
+    #[test]
+    fn skill_noise_never_reaches_the_mate_scores() {
+        // Queens worth so much that the evaluation is at its limit
+        let mut params = EvalParams::default();
+        params.piece_values[4] = 10_000;
+        params.mg_tables[4] = [[10_000; 8]; 8];
+        params.eg_tables[4] = [[10_000; 8]; 8];
+        let mut engine = Engine::new(1, Arc::new(params));
+        engine.skill = Some(Skill::new(1));
+        let stop = AtomicBool::new(false);
+        let searcher = Searcher::new(&engine, &stop);
+
+        let (board, _) = fen::parse_fen("6k1/8/8/8/8/8/7K/QQQQQ3 w - - 0 1").unwrap();
+        for is_white in [true, false] {
+            assert!(searcher.evaluate(board, 0, is_white).abs() <= MAX_EVAL);
+        }
+
+        // Real mates are left alone
+        let (mated, _) = fen::parse_fen("R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1").unwrap();
+        assert_eq!(searcher.evaluate(mated, 0, false), -MATE_SCORE);
+    }
 }
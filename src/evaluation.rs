@@ -1,80 +1,267 @@
 use crate::*;
+use params::EvalParams;
 
-pub fn evaluate_board(board: Board) -> i32 {
+// Score given to a checkmate, large enough to dominate any material count
+pub const MATE_SCORE: i32 = 100_000;
+// Largest score an evaluation may give, anything above is taken for a mate by the search
+pub const MAX_EVAL: i32 = MATE_SCORE - 1001;
+
+// A full set of minor and major pieces adds up to this phase
+const MAX_PHASE: i32 = 24;
+
+// The separate parts of the evaluation, as shown by the eval command
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Term {
+    Material,
+    PawnTable,
+    KnightTable,
+    BishopTable,
+    RookTable,
+    QueenTable,
+    KingTable,
+    Pawns,
+    Mobility,
+    KingSafety,
+    Pieces,
+}
+
+pub const TERMS: [Term; 11] = [
+    Term::Material,
+    Term::PawnTable,
+    Term::KnightTable,
+    Term::BishopTable,
+    Term::RookTable,
+    Term::QueenTable,
+    Term::KingTable,
+    Term::Pawns,
+    Term::Mobility,
+    Term::KingSafety,
+    Term::Pieces,
+];
+
+impl Term {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Term::Material => "Material",
+            Term::PawnTable => "Pawn PST",
+            Term::KnightTable => "Knight PST",
+            Term::BishopTable => "Bishop PST",
+            Term::RookTable => "Rook PST",
+            Term::QueenTable => "Queen PST",
+            Term::KingTable => "King PST",
+            Term::Pawns => "Pawn structure",
+            Term::Mobility => "Mobility",
+            Term::KingSafety => "King safety",
+            Term::Pieces => "Bishops & rooks",
+        }
+    }
+}
+
+// Per-term, per-side breakdown of the evaluation as (middlegame, endgame) scores,
+// each side's scores counting in its own favour
+#[derive(Clone, Copy, Debug)]
+pub struct EvalTrace {
+    pub white: [(i32, i32); TERMS.len()],
+    pub black: [(i32, i32); TERMS.len()],
+    pub phase: i32,
+}
+
+impl EvalTrace {
+    pub fn add(&mut self, term: Term, is_white: bool, mg: i32, eg: i32) {
+        let side = if is_white {
+            &mut self.white
+        } else {
+            &mut self.black
+        };
+        side[term as usize].0 += mg;
+        side[term as usize].1 += eg;
+    }
+
+    // White's minus Black's score for a term
+    pub fn term_total(&self, term: Term) -> (i32, i32) {
+        let (white_mg, white_eg) = self.white[term as usize];
+        let (black_mg, black_eg) = self.black[term as usize];
+        (white_mg - black_mg, white_eg - black_eg)
+    }
+
+    // Blend the middlegame and endgame scores of all terms by the game phase,
+    // from White's point of view
+    pub fn total(&self) -> i32 {
+        let (mut mg_score, mut eg_score) = (0, 0);
+        for term in TERMS {
+            let (mg, eg) = self.term_total(term);
+            mg_score += mg;
+            eg_score += eg;
+        }
+
+        (mg_score * self.phase + eg_score * (MAX_PHASE - self.phase)) / MAX_PHASE
+    }
+}
+
+impl std::fmt::Display for EvalTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let pawns = |score: i32| format!("{:6.2}", score as f64 / 100.0);
+
+        writeln!(
+            f,
+            "{:>16} |    White      |    Black      |    Total",
+            "Term"
+        )?;
+        writeln!(
+            f,
+            "{:>16} |   MG     EG   |   MG     EG   |   MG     EG",
+            ""
+        )?;
+        writeln!(
+            f,
+            "{:->17}+---------------+---------------+--------------",
+            ""
+        )?;
+        for term in TERMS {
+            let (white_mg, white_eg) = self.white[term as usize];
+            let (black_mg, black_eg) = self.black[term as usize];
+            let (total_mg, total_eg) = self.term_total(term);
+            writeln!(
+                f,
+                "{:>16} | {} {} | {} {} | {} {}",
+                term.name(),
+                pawns(white_mg),
+                pawns(white_eg),
+                pawns(black_mg),
+                pawns(black_eg),
+                pawns(total_mg),
+                pawns(total_eg)
+            )?;
+        }
+        writeln!(
+            f,
+            "{:->17}+---------------+---------------+--------------",
+            ""
+        )?;
+        writeln!(f, "Game phase: {} / {}", self.phase, MAX_PHASE)?;
+        write!(
+            f,
+            "Final evaluation: {} (White side)",
+            pawns(self.total()).trim()
+        )
+    }
+}
+
+pub fn evaluate_board(board: Board, params: &EvalParams) -> i32 {
     // White is maximizer
     // Black is minimizer
 
-    let mut white_score = 0;
-    let mut black_score = 0;
-
     if let Some(winner) = check_for_mates(board) {
         if winner == Colour::White {
-            return std::i32::MAX;
+            return MATE_SCORE;
         } else {
-            return std::i32::MIN;
+            return -MATE_SCORE;
         }
     }
 
-    // P = 100
-    // N = 320
-    // B = 330
-    // R = 500
-    // Q = 900
-    // K = 20000
+    // Loaded weights can add up to more than any real position is worth
+    trace_board(board, params)
+        .total()
+        .clamp(-MAX_EVAL, MAX_EVAL)
+}
+
+// Evaluate every term of the position separately, without looking for mates
+pub fn trace_board(board: Board, params: &EvalParams) -> EvalTrace {
+    // Middlegame and endgame scores are kept apart and blended by the game phase
+    let mut trace = EvalTrace {
+        white: [(0, 0); TERMS.len()],
+        black: [(0, 0); TERMS.len()],
+        phase: game_phase(board),
+    };
+
+    // Kings are not counted, since both sides always have one
     for row in 0..8 {
         for col in 0..8 {
             let piece = board.tiles[row][col].piece;
 
             if piece.piece_type != Type::Empty {
+                let (term, kind) = match piece.piece_type {
+                    Type::Pawn(_) => (Term::PawnTable, 0),
+                    Type::Knight => (Term::KnightTable, 1),
+                    Type::Bishop => (Term::BishopTable, 2),
+                    Type::Rook(_) => (Term::RookTable, 3),
+                    Type::Queen => (Term::QueenTable, 4),
+                    _ => (Term::KingTable, 5),
+                };
                 let piece_value = match piece.piece_type {
-                    Type::Pawn(_) => 100,
-                    Type::Knight => 320,
-                    Type::Bishop => 330,
-                    Type::Rook(_) => 500,
-                    Type::Queen => 900,
-                    Type::King(_) => 20000,
-                    _ => 0,
+                    Type::King(_) => 0,
+                    _ => params.piece_values[kind],
                 };
 
-                if piece.colour == Colour::White {
-                    let white_pos_value = match piece.piece_type {
-                        Type::Pawn(_) => PAWN_TABLE[row][col],
-                        Type::Knight => KNIGHT_TABLE[row][col],
-                        Type::Bishop => BISHOP_TABLE[row][col],
-                        Type::Rook(_) => ROOK_TABLE[row][col],
-                        Type::Queen => QUEEN_TABLE[row][col],
-                        Type::King(_) => KING_TABLE[row][col],
-                        _ => 0,
-                    };
-
-                    white_score += piece_value + white_pos_value;
-                } else {
-                    let black_pos_value = match piece.piece_type {
-                        Type::Pawn(_) => PAWN_TABLE[7 - row][col],
-                        Type::Knight => KNIGHT_TABLE[7 - row][col],
-                        Type::Bishop => BISHOP_TABLE[7 - row][col],
-                        Type::Rook(_) => ROOK_TABLE[7 - row][col],
-                        Type::Queen => QUEEN_TABLE[7 - row][col],
-                        Type::King(_) => KING_TABLE[7 - row][col],
-                        _ => 0,
-                    };
-
-                    black_score += piece_value + black_pos_value;
-                }
+                // The tables are from White's point of view, so flip them for Black
+                let is_white = piece.colour == Colour::White;
+                let table_row = if is_white { row } else { 7 - row };
+
+                trace.add(Term::Material, is_white, piece_value, piece_value);
+                trace.add(
+                    term,
+                    is_white,
+                    params.mg_tables[kind][table_row][col],
+                    params.eg_tables[kind][table_row][col],
+                );
             }
         }
     }
 
-    return white_score - black_score;
+    pawns::evaluate_pawns(&board, params, &mut trace);
+    pieces::evaluate_pieces(&board, params, &mut trace);
+
+    trace
+}
+
+// Game phase from the remaining minor and major pieces,
+// MAX_PHASE at the start of the game down to 0 when only kings and pawns are left
+pub fn game_phase(board: Board) -> i32 {
+    let mut phase = 0;
+
+    for tile in board.tiles.iter().flatten() {
+        phase += match tile.piece.piece_type {
+            Type::Knight | Type::Bishop => 1,
+            Type::Rook(_) => 2,
+            Type::Queen => 4,
+            _ => 0,
+        };
+    }
+
+    // Promotions can push the phase past a full set of pieces
+    phase.min(MAX_PHASE)
 }
 
+// Default piece values of pawns, knights, bishops, rooks and queens
+pub const PIECE_VALUES: [i32; 5] = [100, 320, 330, 500, 900];
+
+// Default tables of pawns, knights, bishops, rooks, queens and kings
+pub const MG_TABLES: [[[i32; 8]; 8]; 6] = [
+    PAWN_MG_TABLE,
+    KNIGHT_MG_TABLE,
+    BISHOP_MG_TABLE,
+    ROOK_MG_TABLE,
+    QUEEN_MG_TABLE,
+    KING_MG_TABLE,
+];
+pub const EG_TABLES: [[[i32; 8]; 8]; 6] = [
+    PAWN_EG_TABLE,
+    KNIGHT_EG_TABLE,
+    BISHOP_EG_TABLE,
+    ROOK_EG_TABLE,
+    QUEEN_EG_TABLE,
+    KING_EG_TABLE,
+];
+
 ////////////////////////////////////////
 //Tomasz Michniewski's evaluation tables
 ////////////////////////////////////////
 
+// Middlegame tables
+
 //pawns
 #[rustfmt::skip]
-const PAWN_TABLE: [[i32; 8]; 8] = [
+const PAWN_MG_TABLE: [[i32; 8]; 8] = [
     [0,  0,  0,  0,  0,  0,  0,  0],
     [50, 50, 50, 50, 50, 50, 50, 50],
     [10, 10, 20, 30, 30, 20, 10, 10],
@@ -87,7 +274,7 @@ const PAWN_TABLE: [[i32; 8]; 8] = [
 
 //knights
 #[rustfmt::skip]
-const KNIGHT_TABLE: [[i32; 8]; 8] = [
+const KNIGHT_MG_TABLE: [[i32; 8]; 8] = [
     [-50,-40,-30,-30,-30,-30,-40,-50],
     [-40,-20,  0,  0,  0,  0,-20,-40],
     [-30,  0, 10, 15, 15, 10,  0,-30],
@@ -100,7 +287,7 @@ const KNIGHT_TABLE: [[i32; 8]; 8] = [
 
 //bishops
 #[rustfmt::skip]
-const BISHOP_TABLE: [[i32; 8]; 8] = [
+const BISHOP_MG_TABLE: [[i32; 8]; 8] = [
     [-20,-10,-10,-10,-10,-10,-10,-20],
     [-10,  0,  0,  0,  0,  0,  0,-10],
     [-10,  0,  5, 10, 10,  5,  0,-10],
@@ -113,7 +300,7 @@ const BISHOP_TABLE: [[i32; 8]; 8] = [
 
 //rooks
 #[rustfmt::skip]
-const ROOK_TABLE: [[i32; 8]; 8] = [
+const ROOK_MG_TABLE: [[i32; 8]; 8] = [
     [ 0,  0,  0,  0,  0,  0,  0,  0],
     [ 5, 10, 10, 10, 10, 10, 10,  5],
     [-5,  0,  0,  0,  0,  0,  0, -5],
@@ -126,7 +313,7 @@ const ROOK_TABLE: [[i32; 8]; 8] = [
 
 //queens
 #[rustfmt::skip]
-const QUEEN_TABLE: [[i32; 8]; 8] = [
+const QUEEN_MG_TABLE: [[i32; 8]; 8] = [
     [-20,-10,-10, -5, -5,-10,-10,-20],
     [-10,  0,  0,  0,  0,  0,  0,-10],
     [-10,  0,  5,  5,  5,  5,  0,-10],
@@ -139,7 +326,7 @@ const QUEEN_TABLE: [[i32; 8]; 8] = [
 
 //kings
 #[rustfmt::skip]
-const KING_TABLE: [[i32; 8]; 8] = [
+const KING_MG_TABLE: [[i32; 8]; 8] = [
     [-30,-40,-40,-50,-50,-40,-40,-30],
     [-30,-40,-40,-50,-50,-40,-40,-30],
     [-30,-40,-40,-50,-50,-40,-40,-30],
@@ -150,15 +337,85 @@ const KING_TABLE: [[i32; 8]; 8] = [
     [ 20, 30, 10,  0,  0, 10, 30, 20],
 ];
 
+// Endgame tables
+// The king endgame table is Michniewski's, the others follow the same scheme:
+// pawns are rewarded for advancing, pieces for centralisation, and nothing is
+// rewarded for hiding behind pawns anymore
+
+//pawns endgame
+#[rustfmt::skip]
+const PAWN_EG_TABLE: [[i32; 8]; 8] = [
+    [  0,  0,  0,  0,  0,  0,  0,  0],
+    [ 80, 80, 80, 80, 80, 80, 80, 80],
+    [ 50, 50, 50, 50, 50, 50, 50, 50],
+    [ 30, 30, 30, 30, 30, 30, 30, 30],
+    [ 15, 15, 15, 15, 15, 15, 15, 15],
+    [  5,  5,  5,  5,  5,  5,  5,  5],
+    [  0,  0,  0,  0,  0,  0,  0,  0],
+    [  0,  0,  0,  0,  0,  0,  0,  0],
+];
+
+//knights endgame
+#[rustfmt::skip]
+const KNIGHT_EG_TABLE: [[i32; 8]; 8] = [
+    [-50,-40,-30,-30,-30,-30,-40,-50],
+    [-40,-20,-10, -5, -5,-10,-20,-40],
+    [-30,-10,  5, 10, 10,  5,-10,-30],
+    [-30, -5, 10, 15, 15, 10, -5,-30],
+    [-30, -5, 10, 15, 15, 10, -5,-30],
+    [-30,-10,  5, 10, 10,  5,-10,-30],
+    [-40,-20,-10, -5, -5,-10,-20,-40],
+    [-50,-40,-30,-30,-30,-30,-40,-50],
+];
+
+//bishops endgame
+#[rustfmt::skip]
+const BISHOP_EG_TABLE: [[i32; 8]; 8] = [
+    [-20,-10,-10,-10,-10,-10,-10,-20],
+    [-10,  0,  0,  0,  0,  0,  0,-10],
+    [-10,  0,  5,  5,  5,  5,  0,-10],
+    [-10,  0,  5, 10, 10,  5,  0,-10],
+    [-10,  0,  5, 10, 10,  5,  0,-10],
+    [-10,  0,  5,  5,  5,  5,  0,-10],
+    [-10,  0,  0,  0,  0,  0,  0,-10],
+    [-20,-10,-10,-10,-10,-10,-10,-20],
+];
+
+//rooks endgame
+#[rustfmt::skip]
+const ROOK_EG_TABLE: [[i32; 8]; 8] = [
+    [ 5,  5,  5,  5,  5,  5,  5,  5],
+    [10, 10, 10, 10, 10, 10, 10, 10],
+    [ 0,  0,  0,  0,  0,  0,  0,  0],
+    [ 0,  0,  0,  0,  0,  0,  0,  0],
+    [ 0,  0,  0,  0,  0,  0,  0,  0],
+    [ 0,  0,  0,  0,  0,  0,  0,  0],
+    [ 0,  0,  0,  0,  0,  0,  0,  0],
+    [ 0,  0,  0,  0,  0,  0,  0,  0],
+];
+
+//queens endgame
+#[rustfmt::skip]
+const QUEEN_EG_TABLE: [[i32; 8]; 8] = [
+    [-20,-10,-10, -5, -5,-10,-10,-20],
+    [-10,  0,  5,  5,  5,  5,  0,-10],
+    [-10,  5, 10, 10, 10, 10,  5,-10],
+    [ -5,  5, 10, 15, 15, 10,  5, -5],
+    [ -5,  5, 10, 15, 15, 10,  5, -5],
+    [-10,  5, 10, 10, 10, 10,  5,-10],
+    [-10,  0,  5,  5,  5,  5,  0,-10],
+    [-20,-10,-10, -5, -5,-10,-10,-20],
+];
+
 //kings endgame
-// #[rustfmt::skip]
-// const KING_ENDGAME_TABLE: [[i32; 8]; 8] = [
-//     [-50,-40,-30,-20,-20,-30,-40,-50],
-//     [-30,-20,-10,  0,  0,-10,-20,-30],
-//     [-30,-10, 20, 30, 30, 20,-10,-30],
-//     [-30,-10, 30, 40, 40, 30,-10,-30],
-//     [-30,-10, 30, 40, 40, 30,-10,-30],
-//     [-30,-10, 20, 30, 30, 20,-10,-30],
-//     [-30,-30,  0,  0,  0,  0,-30,-30],
-//     [-50,-30,-30,-30,-30,-30,-30,-50],
-// ];
+#[rustfmt::skip]
+const KING_EG_TABLE: [[i32; 8]; 8] = [
+    [-50,-40,-30,-20,-20,-30,-40,-50],
+    [-30,-20,-10,  0,  0,-10,-20,-30],
+    [-30,-10, 20, 30, 30, 20,-10,-30],
+    [-30,-10, 30, 40, 40, 30,-10,-30],
+    [-30,-10, 30, 40, 40, 30,-10,-30],
+    [-30,-10, 20, 30, 30, 20,-10,-30],
+    [-30,-30,  0,  0,  0,  0,-30,-30],
+    [-50,-30,-30,-30,-30,-30,-30,-50],
+];
@@ -0,0 +1,230 @@
+use crate::*;
+
+// Read the position and side to move from a FEN string
+// Castling rights decide whether kings and rooks count as moved, and en passant is ignored
+// since the board has no way to represent it
+pub fn parse_fen(fen: &str) -> Result<(Board, bool), String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() < 2 {
+        return Err(format!("Not a FEN: '{}'", fen));
+    }
+
+    let mut board = Board::new();
+    for tile in board.tiles.iter_mut().flatten() {
+        tile.piece = Piece {
+            piece_type: Type::Empty,
+            colour: Colour::White,
+        };
+    }
+
+    let castling = fields.get(2).copied().unwrap_or("-");
+    let ranks: Vec<&str> = fields[0].split('/').collect();
+    if ranks.len() != 8 {
+        return Err(format!("Expected 8 ranks in '{}'", fields[0]));
+    }
+
+    let mut kings = (0, 0);
+    for (row, rank) in ranks.iter().enumerate() {
+        let mut col = 0;
+        for c in rank.chars() {
+            if let Some(empty) = c.to_digit(10) {
+                col += empty as usize;
+                continue;
+            }
+            if col >= 8 {
+                return Err(format!("Rank '{}' has more than 8 tiles", rank));
+            }
+
+            let colour = if c.is_ascii_uppercase() {
+                Colour::White
+            } else {
+                Colour::Black
+            };
+            let is_white = colour == Colour::White;
+            let piece_type = match c.to_ascii_lowercase() {
+                // Only a pawn on its starting rank may still advance two tiles
+                'p' => Type::Pawn(row != if is_white { 6 } else { 1 }),
+                'n' => Type::Knight,
+                'b' => Type::Bishop,
+                'q' => Type::Queen,
+                'r' => {
+                    let right = match (row, col, is_white) {
+                        (7, 7, true) => 'K',
+                        (7, 0, true) => 'Q',
+                        (0, 7, false) => 'k',
+                        (0, 0, false) => 'q',
+                        _ => ' ',
+                    };
+                    Type::Rook(!castling.contains(right))
+                }
+                'k' => {
+                    if is_white {
+                        board.kingpos_w = (row, col);
+                        kings.0 += 1;
+                    } else {
+                        board.kingpos_b = (row, col);
+                        kings.1 += 1;
+                    }
+                    let (home, rights) = if is_white {
+                        ((7, 4), ['K', 'Q'])
+                    } else {
+                        ((0, 4), ['k', 'q'])
+                    };
+                    Type::King((row, col) != home || !rights.iter().any(|&r| castling.contains(r)))
+                }
+                _ => return Err(format!("Unknown piece '{}'", c)),
+            };
+
+            board.tiles[row][col].piece = Piece { piece_type, colour };
+            col += 1;
+        }
+
+        if col != 8 {
+            return Err(format!("Rank '{}' does not have 8 tiles", rank));
+        }
+    }
+
+    if kings != (1, 1) {
+        return Err("Both sides need exactly one king".to_string());
+    }
+
+    let is_white = match fields[1] {
+        "w" => true,
+        "b" => false,
+        side => return Err(format!("Unknown side to move '{}'", side)),
+    };
+
+    Ok((board, is_white))
+}
+
+// The fullmove number of a FEN, 1 when it has none
+pub fn fullmove_number(fen: &str) -> usize {
+    fen.split_whitespace()
+        .nth(5)
+        .and_then(|number| number.parse::<usize>().ok())
+        .map_or(1, |number| number.max(1))
+}
+
+// Write the position as FEN, without en passant and move counters since the board doesn't keep them
+pub fn to_fen(board: &Board, is_white: bool) -> String {
+    let mut placement = Vec::new();
+    for row in board.tiles.iter() {
+        let mut rank = String::new();
+        let mut empty = 0;
+        for tile in row.iter() {
+            let letter = match tile.piece.piece_type {
+                Type::Empty => {
+                    empty += 1;
+                    continue;
+                }
+                Type::Pawn(_) => 'p',
+                Type::Knight => 'n',
+                Type::Bishop => 'b',
+                Type::Rook(_) => 'r',
+                Type::Queen => 'q',
+                Type::King(_) => 'k',
+            };
+            if empty > 0 {
+                rank.push_str(&empty.to_string());
+                empty = 0;
+            }
+            if tile.piece.colour == Colour::White {
+                rank.push(letter.to_ascii_uppercase());
+            } else {
+                rank.push(letter);
+            }
+        }
+        if empty > 0 {
+            rank.push_str(&empty.to_string());
+        }
+        placement.push(rank);
+    }
+
+    let mut castling = String::new();
+    let rights = [
+        ((7, 4), (7, 7), Colour::White, 'K'),
+        ((7, 4), (7, 0), Colour::White, 'Q'),
+        ((0, 4), (0, 7), Colour::Black, 'k'),
+        ((0, 4), (0, 0), Colour::Black, 'q'),
+    ];
+    for (king, rook, colour, right) in rights {
+        let king = board.tiles[king.0][king.1].piece;
+        let rook = board.tiles[rook.0][rook.1].piece;
+        if king.piece_type == Type::King(false)
+            && king.colour == colour
+            && rook.piece_type == Type::Rook(false)
+            && rook.colour == colour
+        {
+            castling.push(right);
+        }
+    }
+    if castling.is_empty() {
+        castling.push('-');
+    }
+
+    format!(
+        "{} {} {} - 0 1",
+        placement.join("/"),
+        if is_white { "w" } else { "b" },
+        castling
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_and_writes_the_starting_position() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let (board, is_white) = parse_fen(start).unwrap();
+        assert!(is_white);
+        assert!(board.tiles == Board::new().tiles);
+        assert_eq!(to_fen(&board, is_white), start);
+    }
+
+    #[test]
+    fn round_trips_positions() {
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 0 1",
+            "r3k2r/8/8/8/8/8/8/R3K2R b Kq - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 w - - 0 1",
+        ] {
+            let (board, is_white) = parse_fen(fen).unwrap();
+            assert_eq!(to_fen(&board, is_white), fen);
+        }
+    }
+
+    #[test]
+    fn castling_rights_decide_what_has_moved() {
+        let (board, _) = parse_fen("r3k2r/8/8/8/8/8/8/R3K2R w Kq - 0 1").unwrap();
+        assert_eq!(board.tiles[7][7].piece.piece_type, Type::Rook(false));
+        assert_eq!(board.tiles[7][0].piece.piece_type, Type::Rook(true));
+        assert_eq!(board.tiles[7][4].piece.piece_type, Type::King(false));
+        assert_eq!(board.tiles[0][0].piece.piece_type, Type::Rook(false));
+        assert_eq!(board.kingpos_w, (7, 4));
+        assert_eq!(board.kingpos_b, (0, 4));
+    }
+
+    #[test]
+    fn rejects_broken_fens() {
+        for fen in [
+            "",
+            "8/8/8/8/8/8/8/8 w - - 0 1",
+            "4k3/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/9/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/8/8/8/8/8/8/4K3 x - - 0 1",
+            "4k3/8/8/8/8/8/8/4X3 w - - 0 1",
+        ] {
+            assert!(parse_fen(fen).is_err(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn reads_the_fullmove_number() {
+        assert_eq!(fullmove_number("4k3/8/8/8/8/8/8/4K3 w - - 3 40"), 40);
+        assert_eq!(fullmove_number("4k3/8/8/8/8/8/8/4K3 w - - 0 0"), 1);
+        assert_eq!(fullmove_number("4k3/8/8/8/8/8/8/4K3 w -"), 1);
+    }
+}
@@ -0,0 +1,49 @@
+use std::io::stdin;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+// All input goes through one thread reading stdin, so the user can type while Magnus is
+// thinking without the line being swallowed by a read that is still waiting afterwards
+static LINES: OnceLock<Mutex<Receiver<String>>> = OnceLock::new();
+
+fn lines() -> &'static Mutex<Receiver<String>> {
+    LINES.get_or_init(|| {
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || loop {
+            let mut line = String::new();
+            match stdin().read_line(&mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+
+        Mutex::new(receiver)
+    })
+}
+
+// Block until the user enters a line and append it to buf, exits when stdin is closed
+pub fn read_line(buf: &mut String) {
+    match lines().lock().unwrap().recv() {
+        Ok(line) => buf.push_str(&line),
+        Err(_) => std::process::exit(0),
+    }
+}
+
+// Wait at most timeout for the user to enter a line
+pub fn read_line_timeout(timeout: Duration) -> Option<String> {
+    match lines().lock().unwrap().recv_timeout(timeout) {
+        Ok(line) => Some(line),
+        Err(RecvTimeoutError::Timeout) => None,
+        Err(RecvTimeoutError::Disconnected) => std::process::exit(0),
+    }
+}
+
+// Wait for the user to enter a line until the deadline passes
+pub fn read_line_until(deadline: Instant) -> Option<String> {
+    read_line_timeout(deadline.saturating_duration_since(Instant::now()))
+}
@@ -1,18 +1,94 @@
+mod analysis;
+mod book;
+mod clock;
+mod endgame;
 mod engine;
 mod evaluation;
+mod fen;
+mod input;
 mod moves;
+mod nnue;
+mod notation;
+mod params;
+mod pawns;
+mod pgn;
+mod pieces;
+mod record;
+mod review;
+mod selfplay;
+mod settings;
+mod skill;
+mod tournament;
+mod tt;
+mod tune;
 mod types;
+mod uci;
+mod zobrist;
 use ansi_term::Colour::{Red, White, RGB};
-use engine::get_all_moves;
+use clock::Clock;
+use engine::{get_all_moves, SearchHandle, SearchResult};
+use input::{read_line, read_line_timeout, read_line_until};
 use moves::*;
-use std::io::{stdin, Write};
+use record::GameRecord;
+use settings::{settings_menu, Settings};
+use skill::Skill;
+use std::io::Write;
+use std::time::{Duration, Instant};
 use types::*;
 
+// Lines shown by 'analyse' without a number, and the depth they are searched to
+const DEFAULT_ANALYSIS_LINES: usize = 3;
+const ANALYSIS_DEPTH: i32 = 5;
+// Depth of the quick search behind 'hint'
+const HINT_DEPTH: i32 = 4;
+// Magnus accepts a draw offer when its search at this depth doesn't see it ahead by more than
+// DRAW_ACCEPT_SCORE centipawns
+const DRAW_DEPTH: i32 = 4;
+const DRAW_ACCEPT_SCORE: i32 = 25;
+// Depth limit of a full strength search on the clock, which is stopped by its time budget
+const TIMED_DEPTH: i32 = 32;
+
 fn main() {
+    if std::env::args().nth(1).as_deref() == Some("tune") {
+        tune::run(std::env::args().skip(2));
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("match") {
+        tournament::run(std::env::args().skip(2));
+        return;
+    }
+    if std::env::args().nth(1).as_deref() == Some("uci") {
+        uci::run();
+        return;
+    }
+
+    let mut settings = Settings::from_args();
+    loop {
+        main_menu(&mut settings);
+    }
+}
+
+fn main_menu(settings: &mut Settings) {
     clear_screen();
     let board = Board::new();
     arrow_print("Welcome to C-Chess!", true);
     arrow_print("Input 'exit' to exit the application at anytime.", false);
+    arrow_print(
+        "Input 'eval' during your turn to see how Magnus evaluates the position.",
+        false,
+    );
+    arrow_print(
+        "Input 'analyse' or 'analyse <N>' to see Magnus' best N moves and their lines.",
+        false,
+    );
+    arrow_print(
+        "Input 'hint' once to see which piece to move and again to see where to.",
+        false,
+    );
+    arrow_print(
+        "Input 'resign' to give up or 'draw' to offer a draw.",
+        false,
+    );
     arrow_print(
         "Input is taken as such: <LETTER><NUMBER><LETTER><NUMBER>",
         true,
@@ -20,7 +96,7 @@ fn main() {
     arrow_print("Alternatively interpreted as: <FROM><TO>", true);
     arrow_print("Examples: a1a8, B1b2, c2f2", true);
     arrow_print(
-        "What do you want to play?\n\n(1) Local Multiplayer\n(2) Singleplayer vs Computer\n(3) Computer vs Computer\n",
+        "What do you want to play?\n\n(1) Local Multiplayer\n(2) Singleplayer vs Computer\n(3) Computer vs Computer\n(4) Settings\n(5) Endgame Training\n(6) Analysis Board\n",
         false,
     );
 
@@ -28,327 +104,468 @@ fn main() {
         print!("{} ", White.bold().paint(">>>"));
         std::io::stdout().flush().unwrap();
         let mut input = String::new();
-        stdin().read_line(&mut input).unwrap();
+        read_line(&mut input);
+
+        if input.trim() == "exit" {
+            std::process::exit(0);
+        }
 
-        match input.trim().parse::<usize>().unwrap() {
-            1 => mp_game_loop(board),
-            2 => sp_game_loop(board),
-            3 => pc_game_loop(board),
+        match input.trim().parse::<usize>() {
+            Ok(1) => mp_game_loop(board, settings),
+            Ok(2) => sp_game_loop(board, settings),
+            Ok(3) => selfplay::watch_game(settings),
+            Ok(4) => settings_menu(settings),
+            Ok(5) => training_game_loop(settings),
+            Ok(6) => analysis::analysis_board(settings),
             _ => {
                 arrow_print("Invalid input!", true);
                 continue;
             }
         }
+
+        return;
     }
 }
 
-fn mp_game_loop(mut board: Board) {
+fn mp_game_loop(mut board: Board, settings: &Settings) {
+    let mut record = GameRecord::new(board, true);
+    let mut clock = settings.time_control.map(Clock::new);
     clear_draw(board, true);
-    loop {
-        new_turn(&mut board, true);
+    let winner = loop {
+        match new_turn(
+            &mut board,
+            true,
+            settings,
+            &mut record,
+            clock.as_mut(),
+            Opponent::Player,
+        ) {
+            Turn::Move(_) => {}
+            Turn::Resign => break resign(board, true),
+            Turn::Draw => break draw_agreed(board),
+            Turn::Flag => break flag_fall(board, true),
+        }
         if let Some(winner) = check_for_mates(board) {
             clear_draw(board, true);
             arrow_print(&format!("{} Wins!", winner.ctos()), true);
-            break;
+            break Some(winner);
         }
 
-        new_turn(&mut board, false);
+        match new_turn(
+            &mut board,
+            false,
+            settings,
+            &mut record,
+            clock.as_mut(),
+            Opponent::Player,
+        ) {
+            Turn::Move(_) => {}
+            Turn::Resign => break resign(board, false),
+            Turn::Draw => break draw_agreed(board),
+            Turn::Flag => break flag_fall(board, false),
+        }
         if let Some(winner) = check_for_mates(board) {
             clear_draw(board, false);
             arrow_print(&format!("{} Wins!", winner.ctos()), true);
-            break;
+            break Some(winner);
         }
-    }
+    };
+    print_hints_used(&record, true);
+    print_hints_used(&record, false);
+    review::offer_review(&record, settings, ["White", "Black"], pgn_result(winner));
     arrow_print("Press Enter to exit.", true);
     let mut input = String::new();
-    stdin().read_line(&mut input).unwrap();
-    main();
+    read_line(&mut input);
 }
 
-fn pc_game_loop(mut board: Board) {
-    arrow_print("What difficulty do you want to play at?", true);
-    arrow_print(
-        "The higher the difficulty, the longer the computer will think",
-        true,
-    );
-    arrow_print(
-        "A difficulty higher than 4 will result in long turns!",
-        true,
-    );
-    arrow_print("1. ", false);
-    arrow_print("2. ", false);
-    arrow_print("3. <-- Fast games", false);
-    arrow_print("4. <-- Recommended", false);
-    arrow_print("5. ", false);
-
-    let mut difficulty;
-    loop {
-        let mut input = String::new();
-        print!(">>> ");
-        std::io::stdout().flush().unwrap();
-        stdin().read_line(&mut input).unwrap();
-        difficulty = match input.trim().parse::<usize>() {
-            Ok(n) => n,
-            Err(_) => {
-                arrow_print("Invalid input!", true);
-                continue;
-            }
-        };
-
-        if difficulty > 5 {
-            arrow_print("Invalid input!", true);
-            continue;
-        }
-
-        break;
-    }
-
-    let mut last_white_move = (0, 0);
-    let mut white_stalemate = 0;
-    let mut last_black_move = (0, 0);
-    let mut black_stalemate = 0;
+fn sp_game_loop(mut board: Board, settings: &Settings) {
+    clear_screen();
+    let skill = ask_skill();
+    let human = ask_colour();
 
-    // make 5 random moves to make the game more interesting
-    for _ in 0..3 {
-        // use fastrand crate for randomness
-        let mut all_moves = get_all_moves(board, true);
-        all_moves.retain(|x| x.1.len() != 0);
-        let move_ = &all_moves[fastrand::usize(0..all_moves.len())];
+    let list_of_replies: [&str; 14] = [
+        "This looks like a good move!",
+        "I think I'll do this...",
+        "What about...",
+        "Taste this!",
+        "Now this is getting interesting.",
+        "I'm not sure about this one, what do you think?",
+        "This is a risky move, but it might pay off.",
+        "I have a good feeling about this move.",
+        "Let's see how this plays out...",
+        "I'm feeling confident about this one.",
+        "This might be a long shot, but it's worth a try.",
+        "I mean, I don't mind.",
+        "You have to know this!",
+        "Can you see it?",
+    ];
 
-        match move_piece(
-            &mut board,
-            move_.0,
-            move_.1[fastrand::usize(..move_.1.len())],
-            true,
-        ) {
-            Err(e) => {
-                clear_draw(board, true);
-                input_error(e);
+    let mut engine = settings.engine();
+    engine.skill = Some(skill);
+    let mut record = GameRecord::new(board, true);
+    let mut clock = settings.time_control.map(Clock::new);
+    let depth = search_depth(skill, clock.is_some());
+    // Magnus' expected reply and the search it started on the position after it
+    let mut ponder: Option<(Move, SearchHandle)> = None;
+    let mut last_move = None;
+    let mut is_white = true;
+    clear_draw(board, human);
+    // actual game loop
+    let winner = loop {
+        if is_white == human {
+            let opponent = Opponent::Magnus(&engine);
+            match new_turn(
+                &mut board,
+                human,
+                settings,
+                &mut record,
+                clock.as_mut(),
+                opponent,
+            ) {
+                Turn::Move(move_) => last_move = Some(move_),
+                Turn::Resign => break resign(board, human),
+                Turn::Draw => break draw_agreed(board),
+                Turn::Flag => break flag_fall(board, human),
             }
+            // TODO: fix
+            clear_draw(board, human);
 
-            Ok(_) => {
-                clear_draw(board, true);
-                arrow_print("Doing 6 random moves...", true)
+            if let Some(winner) = check_for_mates(board) {
+                clear_draw(board, human);
+                arrow_print(&format!("{} Wins!", winner.ctos()), true);
+                break Some(winner);
             }
+            is_white = !is_white;
+            continue;
         }
 
-        std::thread::sleep(std::time::Duration::from_millis(500));
-
-        let mut all_moves = get_all_moves(board, false);
-        all_moves.retain(|x| x.1.len() != 0);
-        let move_ = &all_moves[fastrand::usize(0..all_moves.len())];
-
-        match move_piece(
-            &mut board,
-            move_.0,
-            move_.1[fastrand::usize(..move_.1.len())],
-            true,
-        ) {
-            Err(e) => {
-                clear_draw(board, true);
-                input_error(e);
+        println!(
+            "{} Magnus is thinking... (type 'stop' to make it move now)",
+            Red.bold().paint(">>>")
+        );
+        let magnus_start = std::time::Instant::now();
+        let magnus_board = board;
+        if let Some(clock) = clock.as_mut() {
+            clock.start(!human);
+        }
+        let budget = clock.as_ref().map(|clock| clock.budget(!human));
+        let (move_, result) = match book_move(settings, board, !human, last_move) {
+            Some(move_) => {
+                if let Some((_, handle)) = ponder.take() {
+                    handle.stop();
+                    handle.wait();
+                }
+                (move_, None)
             }
-
-            Ok(_) => {
-                clear_draw(board, true);
-                arrow_print("Doing 6 random moves...", true)
+            None => {
+                let handle = match ponder.take() {
+                    // Magnus guessed right, the ponder search simply carries on
+                    Some((expected, handle)) if Some(expected) == last_move => handle,
+                    Some((_, handle)) => {
+                        handle.stop();
+                        handle.wait();
+                        engine.start_search(board, depth, !human)
+                    }
+                    None => engine.start_search(board, depth, !human),
+                };
+                let result = wait_for_search_timed(handle, budget);
+                (result.best_move, Some(result))
             }
+        };
+        if clock.as_mut().is_some_and(|clock| !clock.stop()) {
+            break flag_fall(board, !human);
         }
-
-        std::thread::sleep(std::time::Duration::from_millis(500));
-    }
-
-    loop {
-        let white_start = std::time::Instant::now();
-        let move_ = engine::max_move(board, difficulty as i32);
-        match move_piece(&mut board, move_.0, move_.1, true) {
+        match move_piece(&mut board, move_.0, move_.1, !human) {
             Err(_) => {}
 
-            Ok(_) => {
-                clear_draw(board, true);
-            }
-        }
-        if move_.1 == last_white_move {
-            white_stalemate += 1;
-        } else {
-            white_stalemate = 0;
+            Ok(_) => record.push(move_, 0),
         }
-        last_white_move = move_.0;
+        last_move = Some(move_);
 
-        if let Some(winner) = check_for_mates(board) {
-            clear_draw(board, true);
-            arrow_print(&format!("{} Wins!", winner.ctos()), true);
-            break;
-        }
+        clear_draw(board, human);
         let to_piece = board.tiles[move_.1 .0][move_.1 .1].piece.piece_type;
+        let random_reply = fastrand::usize(..list_of_replies.len());
+        println!(
+            "{} {}",
+            Red.bold().paint(">>>"),
+            White.bold().paint(list_of_replies[random_reply])
+        );
         print!(
-            "{} White moved: {} to {} after {:?}\n",
+            "{} Magnus moved: {} to {} after {:?}\n",
             Red.bold().paint(">>>"),
             Red.bold().paint(to_piece.ttos()),
             Red.bold().paint(reverse_match_input(move_.1)),
-            white_start.elapsed()
+            magnus_start.elapsed()
         );
-        println!("{} Black is thinking...", Red.bold().paint(">>>"));
-        if white_stalemate >= 3 && black_stalemate >= 3 {
-            clear_draw(board, true);
-            arrow_print("Stalemate!", true);
-            break;
+        print_search_info(magnus_board, result.as_ref(), !human);
+        if let Some(clock) = &clock {
+            println!("{} {}", Red.bold().paint(">>>"), clock.display());
         }
-        let black_start = std::time::Instant::now();
-        let move_ = engine::min_move(board, difficulty as i32);
-        match move_piece(&mut board, move_.0, move_.1, false) {
-            Err(_) => {}
 
-            Ok(_) => {
-                clear_draw(board, true);
-            }
-        }
-        if move_.1 == last_black_move {
-            black_stalemate += 1;
-        } else {
-            black_stalemate = 0;
-        }
-        last_black_move = move_.0;
         if let Some(winner) = check_for_mates(board) {
-            clear_draw(board, true);
+            clear_draw(board, human);
             arrow_print(&format!("{} Wins!", winner.ctos()), true);
-            break;
+            break Some(winner);
         }
-        let to_piece = board.tiles[move_.1 .0][move_.1 .1].piece.piece_type;
-        print!(
-            "{} Black moved: {} to {} after {:?}\n",
-            Red.bold().paint(">>>"),
-            Red.bold().paint(to_piece.ttos()),
-            Red.bold().paint(reverse_match_input(move_.1)),
-            black_start.elapsed()
-        );
-        println!("{} White is thinking...", Red.bold().paint(">>>"));
 
-        if white_stalemate >= 3 && black_stalemate >= 3 {
-            clear_draw(board, true);
-            arrow_print("Stalemate!", true);
-            break;
+        // Think on the expected reply while the player is on the move
+        if settings.ponder {
+            if let Some(&expected) = result.as_ref().and_then(|result| result.pv.get(1)) {
+                let mut ponder_board = board;
+                if move_piece(&mut ponder_board, expected.0, expected.1, human).is_ok() {
+                    println!(
+                        "{} Magnus is pondering on {}",
+                        Red.bold().paint(">>>"),
+                        notation::move_to_san(board, expected, human)
+                    );
+                    ponder = Some((expected, engine.start_search(ponder_board, depth, !human)));
+                }
+            }
         }
-    }
+        is_white = !is_white;
+    };
 
+    if let Some((_, handle)) = ponder {
+        handle.stop();
+        handle.wait();
+    }
+    print_hints_used(&record, human);
+    let players = if human {
+        ["Player", "Magnus"]
+    } else {
+        ["Magnus", "Player"]
+    };
+    review::offer_review(&record, settings, players, pgn_result(winner));
     arrow_print("Press Enter to exit.", true);
     let mut input = String::new();
-    stdin().read_line(&mut input).unwrap();
-    main();
+    read_line(&mut input);
 }
 
-fn sp_game_loop(mut board: Board) {
-    clear_screen();
-    arrow_print("What depth do you want to play at?", true);
+// Ask how strong Magnus should play, from a beginner to full strength
+fn ask_skill() -> Skill {
+    arrow_print("What skill level do you want Magnus to play at?", true);
+    arrow_print(
+        "Lower levels think less, misjudge positions and sometimes pick a weaker move",
+        true,
+    );
     arrow_print(
-        "The higher the depth, the longer the engine (called Magnus) will think",
+        "Level 20 is full strength and will result in long turns!",
         true,
     );
-    arrow_print("A depth higher than 4 will result in long turns!", true);
-    arrow_print("1. ", false);
-    arrow_print("2. ", false);
-    arrow_print("3. ", false);
-    arrow_print("4. <<< Recommended", false);
-    arrow_print("5. ", false);
-
-    let mut difficulty;
+    arrow_print("1.  <<< Beginner", false);
+    arrow_print("5.  <<< Casual", false);
+    arrow_print("10. <<< Recommended", false);
+    arrow_print("15. <<< Club player", false);
+    arrow_print("20. <<< Full strength", false);
+
     loop {
         let mut input = String::new();
         print!(">>> ");
         std::io::stdout().flush().unwrap();
-        stdin().read_line(&mut input).unwrap();
-        difficulty = match input.trim().parse::<usize>() {
-            Ok(n) => n,
-            Err(_) => {
-                arrow_print("Invalid input!", true);
-                continue;
+        read_line(&mut input);
+        match input.trim().parse::<u32>() {
+            Ok(level) if (skill::MIN_LEVEL..=skill::MAX_LEVEL).contains(&level) => {
+                return Skill::new(level)
             }
-        };
-
-        if difficulty > 5 {
-            arrow_print("Invalid input!", true);
-            continue;
+            _ => arrow_print("Invalid input!", true),
         }
+    }
+}
 
-        break;
+// Ask which side the player wants to play, returns true for White
+fn ask_colour() -> bool {
+    arrow_print("Which side do you want to play?", true);
+    arrow_print("(1) White\n(2) Black\n(3) Random\n", false);
+
+    loop {
+        let mut input = String::new();
+        print!(">>> ");
+        std::io::stdout().flush().unwrap();
+        read_line(&mut input);
+        match input.trim() {
+            "1" => return true,
+            "2" => return false,
+            "3" => {
+                let is_white = fastrand::bool();
+                arrow_print(
+                    &format!("You play {}.", if is_white { "White" } else { "Black" }),
+                    true,
+                );
+                return is_white;
+            }
+            _ => arrow_print("Invalid input!", true),
+        }
     }
+}
 
-    let list_of_replies: [&str; 14] = [
-        "This looks like a good move!",
-        "I think I'll do this...",
-        "What about...",
-        "Taste this!",
-        "Now this is getting interesting.",
-        "I'm not sure about this one, what do you think?",
-        "This is a risky move, but it might pay off.",
-        "I have a good feeling about this move.",
-        "Let's see how this plays out...",
-        "I'm feeling confident about this one.",
-        "This might be a long shot, but it's worth a try.",
-        "I mean, I don't mind.",
-        "You have to know this!",
-        "Can you see it?",
-    ];
+// Mate a lone king against Magnus, which defends perfectly using the solved endings
+fn training_game_loop(settings: &Settings) {
+    clear_screen();
+    arrow_print("Which ending do you want to practise?", true);
+    arrow_print(
+        "(1) King and queen\n(2) King and rook\n(3) King and pawn\n",
+        false,
+    );
+
+    let ending = loop {
+        print!("{} ", White.bold().paint(">>>"));
+        std::io::stdout().flush().unwrap();
+        let mut input = String::new();
+        read_line(&mut input);
+
+        match input.trim().parse::<usize>() {
+            Ok(1) => break endgame::Ending::Queen,
+            Ok(2) => break endgame::Ending::Rook,
+            Ok(3) => break endgame::Ending::Pawn,
+            _ => arrow_print("Invalid input!", true),
+        }
+    };
+
+    arrow_print("Setting up the position...", true);
+    let mut board = endgame::random_position(ending, 9);
+    let best = endgame::plies_to_mate(&board, true).unwrap_or(0);
+    let engine = settings.engine();
+    let mut record = GameRecord::new(board, true);
+    let mut moves = 0;
 
     clear_draw(board, true);
-    // actual game loop
-    loop {
-        new_turn(&mut board, true);
-        // TODO: fix
-        clear_draw(board, true);
+    arrow_print(
+        &format!(
+            "White to move and mate in {} with best play.",
+            (best + 1) / 2
+        ),
+        true,
+    );
 
-        if let Some(winner) = check_for_mates(board) {
+    loop {
+        if let Turn::Resign = new_turn(
+            &mut board,
+            true,
+            settings,
+            &mut record,
+            None,
+            Opponent::Nobody,
+        ) {
             clear_draw(board, true);
-            arrow_print(&format!("{} Wins!", winner.ctos()), true);
+            arrow_print("You gave up on this ending.", true);
             break;
         }
+        moves += 1;
 
-        println!("{} Magnus is thinking...", Red.bold().paint(">>>"));
-        let black_start = std::time::Instant::now();
-        let move_ = engine::min_move(board, difficulty as i32);
-        match move_piece(&mut board, move_.0, move_.1, false) {
-            Err(_) => {}
-
-            Ok(_) => {}
+        if check_for_mates(board) == Some(Colour::White) {
+            clear_draw(board, true);
+            arrow_print(
+                &format!(
+                    "Checkmate in {} moves! The fastest mate took {}.",
+                    moves,
+                    (best + 1) / 2
+                ),
+                true,
+            );
+            break;
         }
 
-        clear_draw(board, true);
-        let to_piece = board.tiles[move_.1 .0][move_.1 .1].piece.piece_type;
-        let random_reply = fastrand::usize(..list_of_replies.len());
-        println!(
-            "{} {}",
-            Red.bold().paint(">>>"),
-            White.bold().paint(list_of_replies[random_reply])
-        );
-        print!(
-            "{} Magnus moved: {} to {} after {:?}\n",
-            Red.bold().paint(">>>"),
-            Red.bold().paint(to_piece.ttos()),
-            Red.bold().paint(reverse_match_input(move_.1)),
-            black_start.elapsed()
-        );
+        let reply = match endgame::plies_to_mate(&board, false) {
+            Some(_) => wait_for_search(engine.start_search(board, 1, false)).best_move,
+            None => {
+                clear_draw(board, true);
+                arrow_print("That gives away the win, the position is a draw now.", true);
+                break;
+            }
+        };
 
-        if let Some(winner) = check_for_mates(board) {
-            clear_draw(board, true);
-            arrow_print(&format!("{} Wins!", winner.ctos()), true);
+        let san = notation::move_to_san(board, reply, false);
+        if move_piece(&mut board, reply.0, reply.1, false).is_err() {
             break;
         }
+        record.push(reply, 0);
+        clear_draw(board, true);
+        println!("{} Magnus played {}", Red.bold().paint(">>>"), san);
+        if let Some(plies) = endgame::plies_to_mate(&board, true) {
+            println!(
+                "{} Mate in {} with best play",
+                Red.bold().paint(">>>"),
+                (plies + 1) / 2
+            );
+        }
     }
+
+    print_hints_used(&record, true);
     arrow_print("Press Enter to exit.", true);
     let mut input = String::new();
-    stdin().read_line(&mut input).unwrap();
-    main();
+    read_line(&mut input);
+}
+
+// The hint of the current turn, searched for the first time the player asks
+#[derive(Default)]
+struct Hint {
+    move_: Option<Move>,
+    // Times the player asked for it
+    requests: u32,
+}
+
+// How the player's turn ended
+enum Turn {
+    Move(Move),
+    Resign,
+    // The opponent accepted a draw offer
+    Draw,
+    // The flag fell before a move was made
+    Flag,
+}
+
+// Who answers the player's draw offers
+#[derive(Clone, Copy)]
+enum Opponent<'a> {
+    // Another player at the same screen, who is asked
+    Player,
+    // Magnus, who decides by its evaluation
+    Magnus(&'a engine::Engine),
+    // Nobody, draws can't be offered
+    Nobody,
 }
 
-fn new_turn(board: &mut Board, is_white: bool) {
+// Let the player make a move and add it to the game record
+fn new_turn(
+    board: &mut Board,
+    is_white: bool,
+    settings: &Settings,
+    record: &mut GameRecord,
+    mut clock: Option<&mut Clock>,
+    opponent: Opponent,
+) -> Turn {
+    let mut hint = Hint::default();
+    if let Some(clock) = clock.as_deref_mut() {
+        clock.start(is_white);
+    }
+
     loop {
-        if is_white {
-            println!("White's Turn");
+        let turn = if is_white {
+            "White's Turn"
         } else {
-            println!("Black's Turn");
+            "Black's Turn"
+        };
+        match clock.as_deref() {
+            Some(clock) => println!("{}    {}", turn, clock.display()),
+            None => println!("{}", turn),
         }
 
-        let (from, to) = handle_input(*board, is_white);
+        let (from, to) = match handle_input(
+            *board,
+            is_white,
+            settings,
+            &mut hint,
+            clock.as_deref_mut(),
+            opponent,
+        ) {
+            Turn::Move(move_) => move_,
+            turn => {
+                if let Some(clock) = clock.as_deref_mut() {
+                    clock.stop();
+                }
+                return turn;
+            }
+        };
         let white_moves = legal_moves(*board, from, is_white);
         if white_moves.contains(&to) {
             match move_piece(board, from, to, is_white) {
@@ -359,7 +576,13 @@ fn new_turn(board: &mut Board, is_white: bool) {
 
                 Ok(_) => {
                     clear_draw(*board, !is_white);
-                    return;
+                    if let Some(clock) = clock.as_deref_mut() {
+                        if !clock.stop() {
+                            return Turn::Flag;
+                        }
+                    }
+                    record.push((from, to), hint.requests);
+                    return Turn::Move((from, to));
                 }
             }
         } else {
@@ -369,7 +592,14 @@ fn new_turn(board: &mut Board, is_white: bool) {
     }
 }
 
-fn handle_input(board: Board, is_white: bool) -> ((usize, usize), (usize, usize)) {
+fn handle_input(
+    board: Board,
+    is_white: bool,
+    settings: &Settings,
+    hint: &mut Hint,
+    mut clock: Option<&mut Clock>,
+    opponent: Opponent,
+) -> Turn {
     let colour = if is_white {
         Colour::White
     } else {
@@ -377,16 +607,73 @@ fn handle_input(board: Board, is_white: bool) -> ((usize, usize), (usize, usize)
     };
 
     loop {
+        let deadline = clock.as_deref().and_then(|clock| clock.deadline());
         print!("{} ", White.bold().paint(">>>"));
         std::io::stdout().flush().unwrap();
-        let mut input = String::new();
-        stdin().read_line(&mut input).unwrap();
+        let mut input = match read_input(deadline) {
+            Some(input) => input,
+            None => return Turn::Flag,
+        };
         input = input.trim().to_lowercase().to_string();
 
         if input == "exit" {
             std::process::exit(0);
         }
 
+        if input == "eval" {
+            clear_draw(board, is_white);
+            println!("{}", evaluation::trace_board(board, &settings.eval_params));
+            if let Some(network) = &settings.network {
+                let eval = network.evaluate(&network.refresh(&board), true);
+                println!(
+                    "Neural network evaluation: {} (White side)",
+                    engine::format_score(eval)
+                );
+            }
+            continue;
+        }
+
+        if input == "analyse" || input.starts_with("analyse ") {
+            let lines = match input["analyse".len()..].trim() {
+                "" => DEFAULT_ANALYSIS_LINES,
+                n => match n.parse::<usize>() {
+                    Ok(n) if n > 0 => n,
+                    _ => {
+                        arrow_print("Usage: analyse <NUMBER OF MOVES>", true);
+                        continue;
+                    }
+                },
+            };
+            clear_draw(board, is_white);
+            print_analysis(board, is_white, settings, lines);
+            continue;
+        }
+
+        if input == "hint" {
+            show_hint(board, is_white, settings, hint);
+            continue;
+        }
+
+        if input == "resign" {
+            arrow_print("Do you really want to resign? (y/n)", true);
+            match read_input(deadline) {
+                Some(answer) if answer.trim().eq_ignore_ascii_case("y") => return Turn::Resign,
+                Some(_) => {
+                    clear_draw(board, is_white);
+                    continue;
+                }
+                None => return Turn::Flag,
+            }
+        }
+
+        if input == "draw" {
+            match offer_draw(board, is_white, opponent, clock.as_deref_mut()) {
+                Some(true) => return Turn::Draw,
+                Some(false) => continue,
+                None => return Turn::Flag,
+            }
+        }
+
         if input.len() != 4 {
             clear_draw(board, is_white);
             input_error(Error::Length);
@@ -423,10 +710,103 @@ fn handle_input(board: Board, is_white: bool) -> ((usize, usize), (usize, usize)
             continue;
         }
 
-        return (from, to);
+        return Turn::Move((from, to));
     }
 }
 
+// Read a line of input, None if the deadline passes first
+fn read_input(deadline: Option<Instant>) -> Option<String> {
+    match deadline {
+        Some(deadline) => read_line_until(deadline),
+        None => {
+            let mut input = String::new();
+            read_line(&mut input);
+            Some(input)
+        }
+    }
+}
+
+// Offer the opponent a draw, returns whether they accepted or None if the flag fell
+// while they were asked
+fn offer_draw(
+    board: Board,
+    is_white: bool,
+    opponent: Opponent,
+    clock: Option<&mut Clock>,
+) -> Option<bool> {
+    let (side, other) = if is_white {
+        ("White", "Black")
+    } else {
+        ("Black", "White")
+    };
+
+    match opponent {
+        Opponent::Player => {
+            arrow_print(
+                &format!("{} offers a draw. {}, do you accept? (y/n)", side, other),
+                true,
+            );
+            let deadline = clock.as_deref().and_then(|clock| clock.deadline());
+            let accepted = read_input(deadline)?.trim().eq_ignore_ascii_case("y");
+            clear_draw(board, is_white);
+            if !accepted {
+                arrow_print(&format!("{} declines the draw.", other), true);
+            }
+            Some(accepted)
+        }
+        Opponent::Magnus(engine) => {
+            println!(
+                "{} Magnus is considering your offer...",
+                Red.bold().paint(">>>")
+            );
+            // Judged at full strength, a weakened search could misjudge the position, and
+            // the player's clock doesn't run while Magnus thinks
+            let mut engine = engine.clone();
+            engine.skill = None;
+            let search = || engine.start_search(board, DRAW_DEPTH, is_white).wait();
+            let result = match clock {
+                Some(clock) => clock.paused(search),
+                None => search(),
+            };
+            // The search is from the player's side, Magnus' view is the opposite
+            let score = -result.score;
+            let accepted = score <= DRAW_ACCEPT_SCORE;
+            clear_draw(board, is_white);
+            if !accepted {
+                arrow_print(
+                    "Magnus declines the draw, it likes its position too much.",
+                    true,
+                );
+            }
+            Some(accepted)
+        }
+        Opponent::Nobody => {
+            clear_draw(board, is_white);
+            arrow_print("You can't offer a draw here.", true);
+            Some(false)
+        }
+    }
+}
+
+// Announce that a side resigned and return the winner
+fn resign(board: Board, is_white: bool) -> Option<Colour> {
+    let (side, winner) = if is_white {
+        ("White", Colour::Black)
+    } else {
+        ("Black", Colour::White)
+    };
+    clear_draw(board, true);
+    arrow_print(&format!("{} resigns, {} wins!", side, winner.ctos()), true);
+    Some(winner)
+}
+
+// Announce a draw by agreement
+fn draw_agreed(board: Board) -> Option<Colour> {
+    clear_draw(board, true);
+    arrow_print("The players agreed to a draw.", true);
+    None
+}
+
 fn match_input(input: String) -> ((usize, usize), (usize, usize)) {
     let mut chars = input.chars();
     let mut from = String::new();
@@ -682,16 +1062,212 @@ pub fn input_error(error: Error) {
     }
 }
 
-fn clear_draw(board: Board, is_white: bool) {
+// Wait for Magnus to finish thinking, letting the user make it move early
+fn wait_for_search(handle: SearchHandle) -> SearchResult {
+    wait_for_search_timed(handle, None)
+}
+
+// Like wait_for_search, but also make Magnus move once its time budget is used up
+fn wait_for_search_timed(handle: SearchHandle, budget: Option<Duration>) -> SearchResult {
+    let start = Instant::now();
+    while !handle.is_finished() {
+        if budget.is_some_and(|budget| start.elapsed() >= budget) {
+            handle.stop();
+        }
+
+        if let Some(input) = read_line_timeout(std::time::Duration::from_millis(10)) {
+            match input.trim().to_lowercase().as_str() {
+                "stop" | "move now" => handle.stop(),
+                "exit" => std::process::exit(0),
+                _ => arrow_print(
+                    "Magnus is thinking, type 'stop' to make it move now.",
+                    false,
+                ),
+            }
+        }
+    }
+
+    handle.wait()
+}
+
+// A move from the opening book, if one is loaded and knows the position
+fn book_move(
+    settings: &Settings,
+    board: Board,
+    is_white: bool,
+    last_move: Option<Move>,
+) -> Option<Move> {
+    settings
+        .book
+        .as_ref()?
+        .pick_move(board, is_white, last_move)
+}
+
+// Point out the piece Magnus would move, and where to on the second request
+fn show_hint(board: Board, is_white: bool, settings: &Settings, hint: &mut Hint) {
+    let (from, to) = match hint.move_ {
+        Some(move_) => move_,
+        None => {
+            println!(
+                "{} Magnus is looking for a good move... (type 'stop' to see it now)",
+                Red.bold().paint(">>>")
+            );
+            let result =
+                wait_for_search(settings.engine().start_search(board, HINT_DEPTH, is_white));
+            hint.move_ = Some(result.best_move);
+            result.best_move
+        }
+    };
+    hint.requests += 1;
+
+    clear_screen();
+    let piece = board.tiles[from.0][from.1].piece.piece_type;
+    if hint.requests == 1 {
+        board.draw_board_highlighted(is_white, &[from]);
+        arrow_print(
+            &format!(
+                "Hint: move your {} on {}",
+                piece.ttos(),
+                reverse_match_input(from)
+            ),
+            true,
+        );
+    } else {
+        board.draw_board_highlighted(is_white, &[from, to]);
+        arrow_print(
+            &format!(
+                "Hint: {} ({} to {})",
+                notation::move_to_san(board, (from, to), is_white),
+                reverse_match_input(from),
+                reverse_match_input(to)
+            ),
+            true,
+        );
+    }
+}
+
+// How deep Magnus searches: on the clock at full strength its time decides instead
+fn search_depth(skill: Skill, timed: bool) -> i32 {
+    if timed && skill.is_full_strength() {
+        TIMED_DEPTH
+    } else {
+        skill.depth()
+    }
+}
+
+// Announce the result when a side ran out of time: a loss, or a draw if the opponent
+// doesn't have the material to mate
+fn flag_fall(board: Board, is_white: bool) -> Option<Colour> {
+    let (side, opponent) = if is_white {
+        ("White", Colour::Black)
+    } else {
+        ("Black", Colour::White)
+    };
+
+    clear_draw(board, true);
+    if clock::can_mate(&board, !is_white) {
+        arrow_print(
+            &format!("{}'s flag fell, {} wins on time!", side, opponent.ctos()),
+            true,
+        );
+        Some(opponent)
+    } else {
+        arrow_print(
+            &format!(
+                "{}'s flag fell, but {} can't mate. It's a draw!",
+                side,
+                opponent.ctos()
+            ),
+            true,
+        );
+        None
+    }
+}
+
+// The result of a game as written in PGN, None for a draw
+fn pgn_result(winner: Option<Colour>) -> &'static str {
+    match winner {
+        Some(Colour::White) => "1-0",
+        Some(Colour::Black) => "0-1",
+        None => "1/2-1/2",
+    }
+}
+
+// Tell how many hints a side needed during the game
+fn print_hints_used(record: &GameRecord, is_white: bool) {
+    let hints = record.hints(is_white);
+    if hints > 0 {
+        arrow_print(
+            &format!(
+                "{} used {} hint{}, on {}.",
+                if is_white { "White" } else { "Black" },
+                hints,
+                if hints == 1 { "" } else { "s" },
+                record.hinted_moves(is_white).join(", ")
+            ),
+            true,
+        );
+    }
+}
+
+// Search the best moves of a position (MultiPV) and print them with their scores from
+// White's point of view and their lines
+fn print_analysis(board: Board, is_white: bool, settings: &Settings, lines: usize) {
+    let mut engine = settings.engine();
+    engine.multi_pv = lines;
+    println!(
+        "{} Magnus is analysing... (type 'stop' to see the lines so far)",
+        Red.bold().paint(">>>")
+    );
+    let result = wait_for_search(engine.start_search(board, ANALYSIS_DEPTH, is_white));
+    print_lines(board, is_white, &result.lines);
+}
+
+// Print numbered lines with their scores from White's point of view
+pub fn print_lines(board: Board, is_white: bool, lines: &[(i32, Vec<Move>)]) {
+    for (i, (score, line)) in lines.iter().enumerate() {
+        let score = if is_white { *score } else { -score };
+        println!(
+            "{} {:>6}  {}",
+            Red.bold().paint(format!("{:>3}.", i + 1)),
+            engine::format_score(score),
+            notation::line_to_san(board, line, is_white)
+        );
+    }
+}
+
+// Print the engine's evaluation from White's point of view and its expected line
+fn print_search_info(board: Board, result: Option<&engine::SearchResult>, is_white: bool) {
+    let result = match result {
+        Some(result) => result,
+        None => {
+            println!("{} played from the opening book", Red.bold().paint(">>>"));
+            return;
+        }
+    };
+    let score = if is_white {
+        result.score
+    } else {
+        -result.score
+    };
+    println!(
+        "{} eval {}, PV: {}",
+        Red.bold().paint(">>>"),
+        engine::format_score(score),
+        notation::line_to_san(board, &result.pv, is_white)
+    );
+}
+
+pub fn clear_draw(board: Board, is_white: bool) {
     clear_screen();
     board.draw_board(is_white);
 }
 
-fn clear_screen() {
+pub fn clear_screen() {
     print!("\x1B[2J\x1B[1;1H");
 }
 
-fn arrow_print(text: &str, bold: bool) {
+pub fn arrow_print(text: &str, bold: bool) {
     if bold {
         println!(
             "{} {}",
@@ -208,6 +208,7 @@ pub fn move_piece(
 
     board.tiles[to.0][to.1].piece = board.tiles[from.0][from.1].piece;
     board.tiles[from.0][from.1].piece.piece_type = Type::Empty;
+    board.last_move = Some((from, to));
 
     // Pawn swap at edge
     if is_white {
@@ -302,7 +303,7 @@ pub fn pawn_swap(mut board: Board, to: (usize, usize), is_white: bool) {
         print!("{} ", ">>>");
         std::io::stdout().flush().unwrap();
         let mut input = String::new();
-        stdin().read_line(&mut input).unwrap();
+        read_line(&mut input);
         input = input.trim().to_lowercase();
 
         match input.as_str() {
@@ -0,0 +1,212 @@
+use crate::*;
+use evaluation::MAX_EVAL;
+
+// A small efficiently updatable neural network: 768 inputs (colour, piece kind and tile, seen
+// from each side) feed a hidden layer per side, whose clipped outputs are combined into one score
+// The weights file is the raw little-endian i16 layout of a "768 -> N x2 -> 1" network:
+// input weights [768][N], hidden biases [N], output weights [2N] and the output bias,
+// possibly followed by padding
+const INPUTS: usize = 768;
+// Quantisation of the hidden layer and the output weights
+const QA: i32 = 255;
+const QB: i32 = 64;
+// Converts the network output to centipawns
+const SCALE: i32 = 400;
+
+pub struct Network {
+    hidden_size: usize,
+    input_weights: Vec<i16>,
+    hidden_biases: Vec<i16>,
+    output_weights: Vec<i16>,
+    output_bias: i16,
+}
+
+// Hidden layer values from White's and from Black's point of view
+#[derive(Clone)]
+pub struct Accumulator {
+    values: [Vec<i16>; 2],
+}
+
+impl Accumulator {
+    // Overwrite with another accumulator without allocating
+    pub fn copy_from(&mut self, other: &Accumulator) {
+        for (values, other) in self.values.iter_mut().zip(&other.values) {
+            values.copy_from_slice(other);
+        }
+    }
+}
+
+impl Network {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let bytes =
+            std::fs::read(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
+        let numbers: Vec<i16> = bytes
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+
+        // Each hidden neuron has 768 input weights, a bias and two output weights
+        let hidden_size = numbers.len().saturating_sub(1) / (INPUTS + 3);
+        if hidden_size == 0 {
+            return Err(format!("{} is too small to be a network", path));
+        }
+
+        let (input_weights, rest) = numbers.split_at(INPUTS * hidden_size);
+        let (hidden_biases, rest) = rest.split_at(hidden_size);
+        let (output_weights, rest) = rest.split_at(2 * hidden_size);
+
+        Ok(Network {
+            hidden_size,
+            input_weights: input_weights.to_vec(),
+            hidden_biases: hidden_biases.to_vec(),
+            output_weights: output_weights.to_vec(),
+            output_bias: rest[0],
+        })
+    }
+
+    // Compute the accumulator of a position from scratch
+    pub fn refresh(&self, board: &Board) -> Accumulator {
+        let mut accumulator = Accumulator {
+            values: [self.hidden_biases.clone(), self.hidden_biases.clone()],
+        };
+
+        for (row, tiles) in board.tiles.iter().enumerate() {
+            for (col, tile) in tiles.iter().enumerate() {
+                if tile.piece.piece_type != Type::Empty {
+                    self.apply(&mut accumulator, tile.piece, (row, col), true);
+                }
+            }
+        }
+
+        accumulator
+    }
+
+    // Bring the accumulator of before up to date with after, only touching the tiles that
+    // changed, which after a move are at most four
+    pub fn update(&self, accumulator: &mut Accumulator, before: &Board, after: &Board) {
+        for row in 0..8 {
+            for col in 0..8 {
+                let old = before.tiles[row][col].piece;
+                let new = after.tiles[row][col].piece;
+                if feature_piece(old) == feature_piece(new) {
+                    continue;
+                }
+
+                if old.piece_type != Type::Empty {
+                    self.apply(accumulator, old, (row, col), false);
+                }
+                if new.piece_type != Type::Empty {
+                    self.apply(accumulator, new, (row, col), true);
+                }
+            }
+        }
+    }
+
+    // Score in centipawns for the side to move
+    pub fn evaluate(&self, accumulator: &Accumulator, is_white: bool) -> i32 {
+        let (own, enemy) = if is_white {
+            (&accumulator.values[0], &accumulator.values[1])
+        } else {
+            (&accumulator.values[1], &accumulator.values[0])
+        };
+        let (own_weights, enemy_weights) = self.output_weights.split_at(self.hidden_size);
+
+        // Summed in i64, a large hidden layer of large weights would overflow an i32
+        let mut output: i64 = 0;
+        for (values, weights) in [(own, own_weights), (enemy, enemy_weights)] {
+            for (&value, &weight) in values.iter().zip(weights) {
+                output += (value as i32).clamp(0, QA) as i64 * weight as i64;
+            }
+        }
+
+        // Keep whatever the network says below the mate scores, which the search relies on
+        let score =
+            (output / QA as i64 + self.output_bias as i64) * SCALE as i64 / (QA * QB) as i64;
+        score.clamp(-MAX_EVAL as i64, MAX_EVAL as i64) as i32
+    }
+
+    // Add or remove the weights of a piece on a tile in both perspectives
+    fn apply(&self, accumulator: &mut Accumulator, piece: Piece, pos: (usize, usize), add: bool) {
+        for (perspective, values) in accumulator.values.iter_mut().enumerate() {
+            let start = feature(piece, pos, perspective == 0) * self.hidden_size;
+            let weights = &self.input_weights[start..start + self.hidden_size];
+
+            // Wrapping like the i16 arithmetic of other NNUE engines, a network whose sums
+            // don't fit is broken anyway but must not panic
+            for (value, &weight) in values.iter_mut().zip(weights) {
+                if add {
+                    *value = value.wrapping_add(weight);
+                } else {
+                    *value = value.wrapping_sub(weight);
+                }
+            }
+        }
+    }
+}
+
+// Whether a pawn, rook or king has moved makes no difference to the network
+fn feature_piece(piece: Piece) -> Option<(usize, Colour)> {
+    let kind = match piece.piece_type {
+        Type::Empty => return None,
+        Type::Pawn(_) => 0,
+        Type::Knight => 1,
+        Type::Bishop => 2,
+        Type::Rook(_) => 3,
+        Type::Queen => 4,
+        Type::King(_) => 5,
+    };
+    Some((kind, piece.colour))
+}
+
+// Input index of a piece on a tile, with tiles counted from a1 and the board mirrored
+// for Black so each perspective sees its own pieces at the bottom
+fn feature(piece: Piece, pos: (usize, usize), white_perspective: bool) -> usize {
+    let (kind, colour) = feature_piece(piece).unwrap();
+    let is_own = (colour == Colour::White) == white_perspective;
+    let rank = if white_perspective { 7 - pos.0 } else { pos.0 };
+
+    (if is_own { 0 } else { 6 } + kind) * 64 + rank * 8 + pos.1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every weight as large as it gets, so the accumulator overflows on the starting position
+    fn huge_network(hidden_size: usize) -> Network {
+        Network {
+            hidden_size,
+            input_weights: vec![i16::MAX; INPUTS * hidden_size],
+            hidden_biases: vec![i16::MAX; hidden_size],
+            output_weights: vec![i16::MAX; 2 * hidden_size],
+            output_bias: i16::MAX,
+        }
+    }
+
+    #[test]
+    fn overflowing_weights_neither_panic_nor_look_like_mate() {
+        let network = huge_network(4096);
+        let mut accumulator = network.refresh(&Board::new());
+        let mut board = Board::new();
+        move_piece(&mut board, (6, 4), (4, 4), true).unwrap();
+        network.update(&mut accumulator, &Board::new(), &board);
+
+        let score = network.evaluate(&accumulator, false);
+        assert!(score.abs() <= MAX_EVAL);
+    }
+
+    #[test]
+    fn update_matches_refresh() {
+        let mut network = huge_network(8);
+        for (i, weight) in network.input_weights.iter_mut().enumerate() {
+            *weight = (i % 97) as i16 - 48;
+        }
+        let before = Board::new();
+        let mut after = before;
+        move_piece(&mut after, (7, 6), (5, 5), true).unwrap();
+
+        let mut accumulator = network.refresh(&before);
+        network.update(&mut accumulator, &before, &after);
+        assert_eq!(accumulator.values, network.refresh(&after).values);
+    }
+}
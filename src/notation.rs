@@ -0,0 +1,293 @@
+use crate::*;
+
+// Convert a move to Standard Algebraic Notation (e.g. "Nf3", "exd5", "O-O", "e8=Q#")
+// The board is the position before the move is made
+pub fn move_to_san(board: Board, move_: Move, is_white: bool) -> String {
+    let (from, to) = move_;
+    let piece = board.tiles[from.0][from.1].piece.piece_type;
+    let is_capture = board.tiles[to.0][to.1].piece.piece_type != Type::Empty;
+    let mut san = String::new();
+
+    match piece {
+        Type::King(_) if from.1.abs_diff(to.1) == 2 => {
+            if to.1 == 6 {
+                san.push_str("O-O");
+            } else {
+                san.push_str("O-O-O");
+            }
+        }
+        Type::Pawn(_) => {
+            if is_capture {
+                san.push_str(&reverse_match_input(from)[..1]);
+                san.push('x');
+            }
+            san.push_str(&reverse_match_input(to));
+
+            // Pawns are always promoted to queens
+            if to.0 == 0 || to.0 == 7 {
+                san.push_str("=Q");
+            }
+        }
+        _ => {
+            san.push(piece_letter(piece));
+            san.push_str(&disambiguation(board, move_, is_white));
+            if is_capture {
+                san.push('x');
+            }
+            san.push_str(&reverse_match_input(to));
+        }
+    }
+
+    let mut new_board = board;
+    if move_piece(&mut new_board, from, to, is_white).is_ok() {
+        if check_for_mates(new_board).is_some() {
+            san.push('#');
+        } else if is_in_check(new_board, !is_white) {
+            san.push('+');
+        }
+    }
+
+    san
+}
+
+// Convert a line of moves to SAN, starting with the given side to move
+pub fn line_to_san(mut board: Board, line: &[Move], mut is_white: bool) -> String {
+    let mut sans = Vec::new();
+
+    for &move_ in line {
+        sans.push(move_to_san(board, move_, is_white));
+        if move_piece(&mut board, move_.0, move_.1, is_white).is_err() {
+            break;
+        }
+        is_white = !is_white;
+    }
+
+    sans.join(" ")
+}
+
+// Read a move given in SAN (e.g. "Nf3", "exd5", "O-O", "e8=Q") or as <FROM><TO> (e.g. "g1f3")
+// Check and annotation marks are ignored, only promotions to a queen are possible
+pub fn parse_move(board: Board, text: &str, is_white: bool) -> Result<Move, String> {
+    let san = text
+        .trim_end_matches(['+', '#', '!', '?'])
+        .replace('0', "O");
+    let home_row = if is_white { 7 } else { 0 };
+
+    let candidates: Vec<Move> = legal_move_list(board, is_white)
+        .into_iter()
+        .filter(|&(from, to)| {
+            let piece = board.tiles[from.0][from.1].piece.piece_type;
+            match san.as_str() {
+                "O-O" | "O-O-O" => {
+                    let col = if san == "O-O" { 6 } else { 2 };
+                    matches!(piece, Type::King(_)) && from == (home_row, 4) && to == (home_row, col)
+                }
+                _ => matches_san(&san, piece, from, to),
+            }
+        })
+        .collect();
+
+    match candidates.len() {
+        1 => Ok(candidates[0]),
+        0 => Err(format!("'{}' is not a legal move", text)),
+        _ => Err(format!("'{}' is ambiguous", text)),
+    }
+}
+
+// All legal moves of a side
+pub fn legal_move_list(board: Board, is_white: bool) -> Vec<Move> {
+    let mut moves = Vec::new();
+    for (from, tos) in engine::get_all_moves(board, is_white) {
+        for to in tos {
+            let mut test_board = board;
+            if move_piece(&mut test_board, from, to, is_white).is_ok() {
+                moves.push((from, to));
+            }
+        }
+    }
+    moves
+}
+
+// Whether a SAN move without castling and check marks, or a <FROM><TO> move, describes a move
+fn matches_san(san: &str, piece: Type, from: (usize, usize), to: (usize, usize)) -> bool {
+    let chars: Vec<char> = san.chars().filter(|&c| c != 'x' && c != '-').collect();
+
+    // <FROM><TO>, optionally followed by the promotion piece
+    let lower: Vec<char> = chars.iter().map(|c| c.to_ascii_lowercase()).collect();
+    if lower.len() >= 4 {
+        if let (Some(start), Some(end)) = (square(&lower[0..2]), square(&lower[2..4])) {
+            let promotion_ok = lower.len() == 4 || (lower.len() == 5 && lower[4] == 'q');
+            return (start, end) == (from, to) && promotion_ok;
+        }
+    }
+
+    // Strip the promotion, only a queen can be chosen
+    let mut chars = chars;
+    let is_promotion = (to.0 == 0 || to.0 == 7) && matches!(piece, Type::Pawn(_));
+    if let Some(&last) = chars.last() {
+        if last.is_ascii_uppercase() {
+            if last != 'Q' || !is_promotion {
+                return false;
+            }
+            chars.pop();
+            if chars.last() == Some(&'=') {
+                chars.pop();
+            }
+        }
+    }
+
+    // The piece letter, missing for pawns
+    let letter = match chars.first() {
+        Some(&c) if c.is_ascii_uppercase() => {
+            chars.remove(0);
+            c
+        }
+        _ => ' ',
+    };
+    if letter != piece_letter(piece) || chars.len() < 2 {
+        return false;
+    }
+
+    // The destination, preceded by the file and/or rank of the origin if needed
+    let (hint, destination) = chars.split_at(chars.len() - 2);
+    if square(destination) != Some(to) {
+        return false;
+    }
+    hint.iter().all(|&c| match c {
+        'a'..='h' => from.1 == c as usize - 'a' as usize,
+        '1'..='8' => from.0 == 8 - c.to_digit(10).unwrap() as usize,
+        _ => false,
+    })
+}
+
+// A tile written like "e4"
+fn square(chars: &[char]) -> Option<(usize, usize)> {
+    match chars {
+        &[file @ 'a'..='h', rank @ '1'..='8'] => Some((
+            8 - rank.to_digit(10).unwrap() as usize,
+            file as usize - 'a' as usize,
+        )),
+        _ => None,
+    }
+}
+
+fn piece_letter(piece: Type) -> char {
+    match piece {
+        Type::Knight => 'N',
+        Type::Bishop => 'B',
+        Type::Rook(_) => 'R',
+        Type::Queen => 'Q',
+        Type::King(_) => 'K',
+        _ => ' ',
+    }
+}
+
+// Find the file and/or rank needed to tell this move apart from other pieces
+// of the same type that can reach the same tile
+fn disambiguation(board: Board, move_: Move, is_white: bool) -> String {
+    let (from, to) = move_;
+    let piece = board.tiles[from.0][from.1].piece;
+    let mut same_file = false;
+    let mut same_rank = false;
+    let mut ambiguous = false;
+
+    for i in 0..8 {
+        for j in 0..8 {
+            // Compare the kind of piece only, a moved and an unmoved rook are still both rooks
+            let other = board.tiles[i][j].piece;
+            if (i, j) == from
+                || other.colour != piece.colour
+                || piece_letter(other.piece_type) != piece_letter(piece.piece_type)
+            {
+                continue;
+            }
+
+            if !legal_moves(board, (i, j), is_white).contains(&to) {
+                continue;
+            }
+
+            let mut test_board = board;
+            if move_piece(&mut test_board, (i, j), to, is_white).is_err() {
+                continue;
+            }
+
+            ambiguous = true;
+            if j == from.1 {
+                same_file = true;
+            }
+            if i == from.0 {
+                same_rank = true;
+            }
+        }
+    }
+
+    let square = reverse_match_input(from);
+    if !ambiguous {
+        String::new()
+    } else if !same_file {
+        square[..1].to_string()
+    } else if !same_rank {
+        square[1..].to_string()
+    } else {
+        square
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(fen: &str) -> (Board, bool) {
+        fen::parse_fen(fen).unwrap()
+    }
+
+    fn san(fen: &str, text: &str) -> String {
+        let (board, is_white) = position(fen);
+        let move_ = parse_move(board, text, is_white).unwrap();
+        move_to_san(board, move_, is_white)
+    }
+
+    #[test]
+    fn writes_simple_moves() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert_eq!(san(start, "g1f3"), "Nf3");
+        assert_eq!(san(start, "e2e4"), "e4");
+        assert_eq!(san("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"), "exd5");
+        assert_eq!(san("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7a8"), "a8=Q+");
+        assert_eq!(san("4k3/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"), "O-O");
+    }
+
+    #[test]
+    fn marks_check_and_mate() {
+        assert_eq!(san("4k3/8/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8+");
+        assert_eq!(san("6k1/5ppp/8/8/8/8/8/R3K3 w - - 0 1", "a1a8"), "Ra8#");
+    }
+
+    #[test]
+    fn disambiguates_by_file_rank_or_both() {
+        assert_eq!(san("4k3/8/8/8/8/8/8/R4R1K w - - 0 1", "a1d1"), "Rad1");
+        assert_eq!(san("4k3/8/8/R7/8/8/8/R6K w - - 0 1", "a1a3"), "R1a3");
+        assert_eq!(san("4k3/8/8/8/Q6Q/8/8/Q6K w - - 0 1", "a4d4"), "Qa4d4");
+        assert_eq!(san("4k3/8/8/8/8/8/8/R5NK w - - 0 1", "a1d1"), "Rd1");
+    }
+
+    #[test]
+    fn disambiguates_moved_and_unmoved_rooks() {
+        // The rook on a1 still has castling rights, the one on f1 has moved
+        let fen = "4k3/8/8/8/8/8/8/R4R1K w Q - 0 1";
+        let (board, _) = position(fen);
+        assert_eq!(board.tiles[7][0].piece.piece_type, Type::Rook(false));
+        assert_eq!(board.tiles[7][5].piece.piece_type, Type::Rook(true));
+        assert_eq!(san(fen, "a1d1"), "Rad1");
+        assert_eq!(san(fen, "f1d1"), "Rfd1");
+    }
+
+    #[test]
+    fn reads_back_what_it_writes() {
+        let fen = "4k3/8/8/8/8/8/8/R4R1K w Q - 0 1";
+        let (board, is_white) = position(fen);
+        assert_eq!(parse_move(board, "Rad1", is_white), Ok(((7, 0), (7, 3))));
+        assert!(parse_move(board, "Rd1", is_white).is_err());
+        assert!(parse_move(board, "Ra2", is_white).is_ok());
+    }
+}
@@ -0,0 +1,355 @@
+use crate::*;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+// Largest weight a parameter file may give, so sums of weights stay far from overflowing
+const MAX_WEIGHT: i32 = 10_000;
+
+// Names of the pieces as used for the table keys, in the order of the tables
+const TABLE_PIECES: [&str; 6] = ["pawn", "knight", "bishop", "rook", "queen", "king"];
+
+// Every weight used by the evaluation, so different personalities and tuned weights
+// can be tried without recompiling
+// Pairs are (middlegame, endgame), tables are from White's point of view
+#[derive(Clone, Debug, PartialEq)]
+pub struct EvalParams {
+    // Pawns, knights, bishops, rooks and queens
+    pub piece_values: [i32; 5],
+    // Pawns, knights, bishops, rooks, queens and kings
+    pub mg_tables: [[[i32; 8]; 8]; 6],
+    pub eg_tables: [[[i32; 8]; 8]; 6],
+
+    pub doubled_penalty: (i32, i32),
+    pub isolated_penalty: (i32, i32),
+    pub backward_penalty: (i32, i32),
+    pub passed_mg_bonus: [i32; 8],
+    pub passed_eg_bonus: [i32; 8],
+    pub free_path_eg_bonus: [i32; 8],
+
+    pub knight_mobility: (i32, i32),
+    pub bishop_mobility: (i32, i32),
+    pub rook_mobility: (i32, i32),
+    pub queen_mobility: (i32, i32),
+    pub knight_typical_moves: i32,
+    pub bishop_typical_moves: i32,
+    pub rook_typical_moves: i32,
+    pub queen_typical_moves: i32,
+
+    pub bishop_pair_bonus: (i32, i32),
+    pub rook_open_file_bonus: (i32, i32),
+    pub rook_semi_open_file_bonus: (i32, i32),
+    pub rook_seventh_rank_bonus: (i32, i32),
+
+    pub pawn_shield_bonus: [i32; 2],
+    pub king_semi_open_file_penalty: i32,
+    pub king_open_file_penalty: i32,
+    pub knight_attack_weight: i32,
+    pub bishop_attack_weight: i32,
+    pub rook_attack_weight: i32,
+    pub queen_attack_weight: i32,
+    pub king_attack_bonus: [i32; 16],
+
+    // Fingerprint of the pawn parameters, worked out once instead of at every evaluation
+    // None after the parameters were changed through fields_mut and until update_pawn_key
+    pawn_key: Option<u64>,
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        let mut params = EvalParams {
+            piece_values: evaluation::PIECE_VALUES,
+            mg_tables: evaluation::MG_TABLES,
+            eg_tables: evaluation::EG_TABLES,
+
+            doubled_penalty: pawns::DOUBLED_PENALTY,
+            isolated_penalty: pawns::ISOLATED_PENALTY,
+            backward_penalty: pawns::BACKWARD_PENALTY,
+            passed_mg_bonus: pawns::PASSED_MG_BONUS,
+            passed_eg_bonus: pawns::PASSED_EG_BONUS,
+            free_path_eg_bonus: pawns::FREE_PATH_EG_BONUS,
+
+            knight_mobility: pieces::KNIGHT_MOBILITY,
+            bishop_mobility: pieces::BISHOP_MOBILITY,
+            rook_mobility: pieces::ROOK_MOBILITY,
+            queen_mobility: pieces::QUEEN_MOBILITY,
+            knight_typical_moves: pieces::KNIGHT_TYPICAL_MOVES,
+            bishop_typical_moves: pieces::BISHOP_TYPICAL_MOVES,
+            rook_typical_moves: pieces::ROOK_TYPICAL_MOVES,
+            queen_typical_moves: pieces::QUEEN_TYPICAL_MOVES,
+
+            bishop_pair_bonus: pieces::BISHOP_PAIR_BONUS,
+            rook_open_file_bonus: pieces::ROOK_OPEN_FILE_BONUS,
+            rook_semi_open_file_bonus: pieces::ROOK_SEMI_OPEN_FILE_BONUS,
+            rook_seventh_rank_bonus: pieces::ROOK_SEVENTH_RANK_BONUS,
+
+            pawn_shield_bonus: pieces::PAWN_SHIELD_BONUS,
+            king_semi_open_file_penalty: pieces::KING_SEMI_OPEN_FILE_PENALTY,
+            king_open_file_penalty: pieces::KING_OPEN_FILE_PENALTY,
+            knight_attack_weight: pieces::KNIGHT_ATTACK_WEIGHT,
+            bishop_attack_weight: pieces::BISHOP_ATTACK_WEIGHT,
+            rook_attack_weight: pieces::ROOK_ATTACK_WEIGHT,
+            queen_attack_weight: pieces::QUEEN_ATTACK_WEIGHT,
+            king_attack_bonus: pieces::KING_ATTACK_BONUS,
+            pawn_key: None,
+        };
+        params.update_pawn_key();
+        params
+    }
+}
+
+impl EvalParams {
+    // Every parameter by the name it has in a parameter file, with its values in file order
+    pub fn fields_mut(&mut self) -> Vec<(String, Vec<&mut i32>)> {
+        // The values may change, so the pawn key has to be worked out again
+        self.pawn_key = None;
+        let mut fields: Vec<(String, Vec<&mut i32>)> = Vec::new();
+
+        fields.push((
+            "piece_values".to_string(),
+            self.piece_values.iter_mut().collect(),
+        ));
+        for (name, table) in TABLE_PIECES.iter().zip(self.mg_tables.iter_mut()) {
+            fields.push((
+                format!("{}_mg_table", name),
+                table.iter_mut().flatten().collect(),
+            ));
+        }
+        for (name, table) in TABLE_PIECES.iter().zip(self.eg_tables.iter_mut()) {
+            fields.push((
+                format!("{}_eg_table", name),
+                table.iter_mut().flatten().collect(),
+            ));
+        }
+
+        let pairs = [
+            ("doubled_penalty", &mut self.doubled_penalty),
+            ("isolated_penalty", &mut self.isolated_penalty),
+            ("backward_penalty", &mut self.backward_penalty),
+            ("knight_mobility", &mut self.knight_mobility),
+            ("bishop_mobility", &mut self.bishop_mobility),
+            ("rook_mobility", &mut self.rook_mobility),
+            ("queen_mobility", &mut self.queen_mobility),
+            ("bishop_pair_bonus", &mut self.bishop_pair_bonus),
+            ("rook_open_file_bonus", &mut self.rook_open_file_bonus),
+            (
+                "rook_semi_open_file_bonus",
+                &mut self.rook_semi_open_file_bonus,
+            ),
+            ("rook_seventh_rank_bonus", &mut self.rook_seventh_rank_bonus),
+        ];
+        for (name, (mg, eg)) in pairs {
+            fields.push((name.to_string(), vec![mg, eg]));
+        }
+
+        let lists: [(&str, &mut [i32]); 5] = [
+            ("passed_mg_bonus", &mut self.passed_mg_bonus),
+            ("passed_eg_bonus", &mut self.passed_eg_bonus),
+            ("free_path_eg_bonus", &mut self.free_path_eg_bonus),
+            ("pawn_shield_bonus", &mut self.pawn_shield_bonus),
+            ("king_attack_bonus", &mut self.king_attack_bonus),
+        ];
+        for (name, values) in lists {
+            fields.push((name.to_string(), values.iter_mut().collect()));
+        }
+
+        let numbers = [
+            ("knight_typical_moves", &mut self.knight_typical_moves),
+            ("bishop_typical_moves", &mut self.bishop_typical_moves),
+            ("rook_typical_moves", &mut self.rook_typical_moves),
+            ("queen_typical_moves", &mut self.queen_typical_moves),
+            (
+                "king_semi_open_file_penalty",
+                &mut self.king_semi_open_file_penalty,
+            ),
+            ("king_open_file_penalty", &mut self.king_open_file_penalty),
+            ("knight_attack_weight", &mut self.knight_attack_weight),
+            ("bishop_attack_weight", &mut self.bishop_attack_weight),
+            ("rook_attack_weight", &mut self.rook_attack_weight),
+            ("queen_attack_weight", &mut self.queen_attack_weight),
+        ];
+        for (name, value) in numbers {
+            fields.push((name.to_string(), vec![value]));
+        }
+
+        fields
+    }
+
+    // Read a parameter file made of lines like "name = 1 2 3", where the values may continue
+    // on the following lines and '#' starts a comment
+    // Parameters missing from the file keep their default value
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path, err))?;
+        let mut values: Vec<(String, usize, Vec<i32>)> = Vec::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let numbers = match line.split_once('=') {
+                Some((name, rest)) => {
+                    values.push((name.trim().to_string(), number + 1, Vec::new()));
+                    rest
+                }
+                None => line,
+            };
+
+            let current = match values.last_mut() {
+                Some((_, _, current)) => current,
+                None => return Err(format!("Line {}: expected <name> = <values>", number + 1)),
+            };
+            for value in numbers.split_whitespace() {
+                match value.parse::<i32>() {
+                    Ok(value) if value.abs() <= MAX_WEIGHT => current.push(value),
+                    Ok(_) => {
+                        return Err(format!(
+                            "Line {}: {} is outside -{} to {}",
+                            number + 1,
+                            value,
+                            MAX_WEIGHT,
+                            MAX_WEIGHT
+                        ))
+                    }
+                    Err(_) => {
+                        return Err(format!("Line {}: '{}' is not a number", number + 1, value))
+                    }
+                }
+            }
+        }
+
+        let mut params = EvalParams::default();
+        let mut fields = params.fields_mut();
+        for (name, line, values) in values {
+            let field = match fields.iter_mut().find(|(field, _)| *field == name) {
+                Some((_, field)) => field,
+                None => return Err(format!("Line {}: unknown parameter '{}'", line, name)),
+            };
+
+            if field.len() != values.len() {
+                return Err(format!(
+                    "Line {}: '{}' expects {} values but has {}",
+                    line,
+                    name,
+                    field.len(),
+                    values.len()
+                ));
+            }
+
+            for (target, value) in field.iter_mut().zip(values) {
+                **target = value;
+            }
+        }
+        drop(fields);
+        params.update_pawn_key();
+        Ok(params)
+    }
+
+    // Write all parameters in the format read by load, tables as 8 rows of 8
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let mut text = String::from("# C-Chess evaluation parameters\n");
+        let mut params = self.clone();
+
+        for (name, values) in params.fields_mut() {
+            let values: Vec<String> = values.iter().map(|value| value.to_string()).collect();
+            if values.len() == 64 {
+                text.push_str(&format!("\n{} =\n", name));
+                for row in values.chunks(8) {
+                    text.push_str(&format!("    {}\n", row.join(" ")));
+                }
+            } else {
+                text.push_str(&format!("\n{} = {}\n", name, values.join(" ")));
+            }
+        }
+
+        std::fs::write(path, text).map_err(|err| format!("Could not write {}: {}", path, err))
+    }
+
+    // Fingerprint of the parameters the cached pawn structure depends on
+    pub fn pawn_key(&self) -> u64 {
+        self.pawn_key.unwrap_or_else(|| self.hash_pawn_params())
+    }
+
+    // Store the pawn key again after changing parameters
+    pub fn update_pawn_key(&mut self) {
+        self.pawn_key = Some(self.hash_pawn_params());
+    }
+
+    fn hash_pawn_params(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.doubled_penalty.hash(&mut hasher);
+        self.isolated_penalty.hash(&mut hasher);
+        self.backward_penalty.hash(&mut hasher);
+        self.passed_mg_bonus.hash(&mut hasher);
+        self.passed_eg_bonus.hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Load a parameter file with the given text
+    fn load_text(name: &str, text: &str) -> Result<EvalParams, String> {
+        let path = std::env::temp_dir().join(format!("c-chess-params-{}.txt", name));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, text).unwrap();
+        let params = EvalParams::load(path);
+        std::fs::remove_file(path).unwrap();
+        params
+    }
+
+    #[test]
+    fn loads_what_it_saves() {
+        let mut params = EvalParams::default();
+        params.piece_values[4] = 950;
+        params.isolated_penalty = (12, 18);
+        params.update_pawn_key();
+
+        let path = std::env::temp_dir().join("c-chess-params-saved.txt");
+        let path = path.to_str().unwrap();
+        params.save(path).unwrap();
+        let loaded = EvalParams::load(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(loaded, params);
+    }
+
+    #[test]
+    fn rejects_weights_out_of_range() {
+        assert!(load_text("in-range", "queen_mobility = 10000 -10000").is_ok());
+        let err = load_text("out-of-range", "piece_values = 100 300 300 500 2000000000");
+        assert!(err.unwrap_err().contains("outside"));
+    }
+
+    #[test]
+    fn pawn_key_follows_the_pawn_parameters() {
+        let default = EvalParams::default();
+        let mut params = load_text("pawns", "doubled_penalty = 11 20").unwrap();
+        assert_ne!(params.pawn_key(), default.pawn_key());
+
+        // Changed through fields_mut the key is worked out again, even before updating it
+        for (name, mut values) in params.fields_mut() {
+            if name == "doubled_penalty" {
+                *values[0] = default.doubled_penalty.0;
+            }
+        }
+        assert_eq!(params.pawn_key(), default.pawn_key());
+        params.update_pawn_key();
+        assert_eq!(params.pawn_key(), default.pawn_key());
+    }
+
+    #[test]
+    fn huge_weights_never_look_like_mate() {
+        let mut params = EvalParams::default();
+        params.piece_values[4] = MAX_WEIGHT;
+        params.mg_tables[4] = [[MAX_WEIGHT; 8]; 8];
+        params.eg_tables[4] = [[MAX_WEIGHT; 8]; 8];
+        params.update_pawn_key();
+
+        let (board, _) = fen::parse_fen("6k1/8/8/8/8/8/7K/QQQQQ3 w - - 0 1").unwrap();
+        let eval = evaluation::evaluate_board(board, &params);
+        assert_eq!(eval, evaluation::MAX_EVAL);
+    }
+}
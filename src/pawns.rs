@@ -0,0 +1,273 @@
+use crate::*;
+use evaluation::{EvalTrace, Term};
+use params::EvalParams;
+use std::cell::RefCell;
+
+// Default penalties and bonuses as (middlegame, endgame)
+pub const DOUBLED_PENALTY: (i32, i32) = (10, 20);
+pub const ISOLATED_PENALTY: (i32, i32) = (10, 15);
+pub const BACKWARD_PENALTY: (i32, i32) = (8, 10);
+
+// Passed pawn bonus by how far the pawn has advanced, from its starting rank to the 7th
+pub const PASSED_MG_BONUS: [i32; 8] = [0, 5, 10, 20, 35, 60, 100, 0];
+pub const PASSED_EG_BONUS: [i32; 8] = [0, 10, 20, 35, 60, 100, 150, 0];
+// Extra endgame bonus for a passed pawn with nothing in the way of promoting
+pub const FREE_PATH_EG_BONUS: [i32; 8] = [0, 5, 10, 15, 30, 50, 80, 0];
+
+const PAWN_TABLE_SIZE: usize = 16384;
+
+// Pawn structure only depends on where the pawns are, so it is cached by the pawn hash
+#[derive(Clone, Copy, Default)]
+struct PawnEntry {
+    key: u64,
+    // Scores of White and Black, each in its own favour
+    mg: [i32; 2],
+    eg: [i32; 2],
+    // Tiles (row * 8 + col) of the passed pawns of White and Black
+    passed: [u64; 2],
+}
+
+thread_local! {
+    // Every search thread has its own table, so no locking is needed
+    // An empty entry has key 0, a real key being 0 is unlikely enough to ignore
+    static PAWN_TABLE: RefCell<Vec<PawnEntry>> =
+        RefCell::new(vec![PawnEntry::default(); PAWN_TABLE_SIZE]);
+}
+
+// Evaluate doubled, isolated, backward and passed pawns
+pub fn evaluate_pawns(board: &Board, params: &EvalParams, trace: &mut EvalTrace) {
+    // Different parameters give a different structure score, so they are part of the key
+    let key = zobrist::hash_pawns(board) ^ params.pawn_key();
+    let entry = PAWN_TABLE.with(|table| {
+        let mut table = table.borrow_mut();
+        let slot = &mut table[(key % PAWN_TABLE_SIZE as u64) as usize];
+        if slot.key != key {
+            *slot = pawn_structure(board, params);
+            slot.key = key;
+        }
+        *slot
+    });
+
+    let mut eg = entry.eg;
+
+    // Whether the path to promotion is free depends on the other pieces, so it isn't cached
+    for (side, &passed) in entry.passed.iter().enumerate() {
+        let is_white = side == 0;
+        for tile in 0..64 {
+            if passed & (1 << tile) == 0 {
+                continue;
+            }
+
+            let (row, col) = (tile / 8, tile % 8);
+            let path_is_free = if is_white {
+                (0..row).all(|r| board.tiles[r][col].piece.piece_type == Type::Empty)
+            } else {
+                (row + 1..8).all(|r| board.tiles[r][col].piece.piece_type == Type::Empty)
+            };
+
+            if path_is_free {
+                eg[side] += params.free_path_eg_bonus[relative_rank(row, is_white)];
+            }
+        }
+    }
+
+    trace.add(Term::Pawns, true, entry.mg[0], eg[0]);
+    trace.add(Term::Pawns, false, entry.mg[1], eg[1]);
+}
+
+// How many ranks a pawn on this row has advanced, 0 being its own back rank
+fn relative_rank(row: usize, is_white: bool) -> usize {
+    if is_white {
+        7 - row
+    } else {
+        row
+    }
+}
+
+fn pawn_structure(board: &Board, params: &EvalParams) -> PawnEntry {
+    // Which rows hold a pawn, per side and file
+    let mut pawns = [[[false; 8]; 8]; 2];
+    for (row, tiles) in board.tiles.iter().enumerate() {
+        for (col, tile) in tiles.iter().enumerate() {
+            if let Type::Pawn(_) = tile.piece.piece_type {
+                let side = if tile.piece.colour == Colour::White {
+                    0
+                } else {
+                    1
+                };
+                pawns[side][col][row] = true;
+            }
+        }
+    }
+
+    let mut entry = PawnEntry::default();
+
+    for side in 0..2 {
+        let is_white = side == 0;
+        let own = &pawns[side];
+        let enemy = &pawns[1 - side];
+        let mut mg = 0;
+        let mut eg = 0;
+
+        for col in 0..8 {
+            let count = own[col].iter().filter(|&&pawn| pawn).count() as i32;
+            if count > 1 {
+                mg -= params.doubled_penalty.0 * (count - 1);
+                eg -= params.doubled_penalty.1 * (count - 1);
+            }
+
+            let adjacent_cols: Vec<usize> = [col.wrapping_sub(1), col + 1]
+                .into_iter()
+                .filter(|&c| c < 8)
+                .collect();
+
+            for row in 0..8 {
+                if !own[col][row] {
+                    continue;
+                }
+
+                // Rows in front of the pawn, towards promotion, and the rest of the board behind it
+                let ahead: Vec<usize> = if is_white {
+                    (0..row).collect()
+                } else {
+                    (row + 1..8).collect()
+                };
+                let level_or_behind: Vec<usize> = if is_white {
+                    (row..8).collect()
+                } else {
+                    (0..=row).collect()
+                };
+
+                let isolated = adjacent_cols
+                    .iter()
+                    .all(|&c| own[c].iter().all(|&pawn| !pawn));
+
+                if isolated {
+                    mg -= params.isolated_penalty.0;
+                    eg -= params.isolated_penalty.1;
+                } else {
+                    // Backward: no friendly pawn can protect it when it advances,
+                    // and the tile in front of it is covered by an enemy pawn
+                    let unsupported = adjacent_cols
+                        .iter()
+                        .all(|&c| level_or_behind.iter().all(|&r| !own[c][r]));
+                    let stop_attacked = ahead.len() >= 2 && {
+                        let attacker_row = if is_white { row - 2 } else { row + 2 };
+                        adjacent_cols.iter().any(|&c| enemy[c][attacker_row])
+                    };
+
+                    if unsupported && stop_attacked {
+                        mg -= params.backward_penalty.0;
+                        eg -= params.backward_penalty.1;
+                    }
+                }
+
+                // Passed: no enemy pawn in front of it on its own or the adjacent files
+                let passed = std::iter::once(col)
+                    .chain(adjacent_cols.iter().copied())
+                    .all(|c| ahead.iter().all(|&r| !enemy[c][r]));
+
+                if passed {
+                    let rank = relative_rank(row, is_white);
+                    mg += params.passed_mg_bonus[rank];
+                    eg += params.passed_eg_bonus[rank];
+                    entry.passed[side] |= 1 << (row * 8 + col);
+                }
+            }
+        }
+
+        entry.mg[side] = mg;
+        entry.eg[side] = eg;
+    }
+
+    entry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn structure(fen: &str) -> PawnEntry {
+        let (board, _) = fen::parse_fen(fen).unwrap();
+        pawn_structure(&board, &EvalParams::default())
+    }
+
+    fn pawn_terms(fen: &str, params: &EvalParams) -> ((i32, i32), (i32, i32)) {
+        let (board, _) = fen::parse_fen(fen).unwrap();
+        let mut trace = EvalTrace {
+            white: [(0, 0); evaluation::TERMS.len()],
+            black: [(0, 0); evaluation::TERMS.len()],
+            phase: 0,
+        };
+        evaluate_pawns(&board, params, &mut trace);
+        (
+            trace.white[Term::Pawns as usize],
+            trace.black[Term::Pawns as usize],
+        )
+    }
+
+    #[test]
+    fn scores_a_passed_isolated_pawn_by_its_rank() {
+        let entry = structure("4k3/8/8/3P4/8/8/8/4K3 w - - 0 1");
+        assert_eq!(entry.mg, [35 - 10, 0]);
+        assert_eq!(entry.eg, [60 - 15, 0]);
+        assert_eq!(entry.passed, [1 << (3 * 8 + 3), 0]);
+    }
+
+    #[test]
+    fn scores_doubled_pawns() {
+        // Both pawns are isolated and passed as well
+        let entry = structure("4k3/8/8/8/8/4P3/4P3/4K3 w - - 0 1");
+        assert_eq!(entry.mg[0], -10 - 2 * 10 + 5 + 10);
+        assert_eq!(entry.eg[0], -20 - 2 * 15 + 10 + 20);
+    }
+
+    #[test]
+    fn a_pawn_in_front_on_an_adjacent_file_stops_a_passer() {
+        let entry = structure("4k3/8/4p3/3P4/8/8/8/4K3 w - - 0 1");
+        assert_eq!(entry.passed, [0, 0]);
+        assert_eq!((entry.mg, entry.eg), ([-10, -10], [-15, -15]));
+    }
+
+    #[test]
+    fn scores_a_backward_pawn() {
+        // d3 can't be protected by e4 and its stop tile is covered by e5
+        let entry = structure("4k3/8/8/4p3/4P3/3P4/8/4K3 w - - 0 1");
+        assert_eq!((entry.mg[0], entry.eg[0]), (-8, -10));
+        assert_eq!((entry.mg[1], entry.eg[1]), (-10, -15));
+    }
+
+    #[test]
+    fn free_path_bonus_depends_on_the_pieces_in_front() {
+        let params = EvalParams::default();
+        let (free, _) = pawn_terms("4k3/8/8/3P4/8/8/8/4K3 w - - 0 1", &params);
+        let (blocked, _) = pawn_terms("3nk3/8/8/3P4/8/8/8/4K3 w - - 0 1", &params);
+        assert_eq!(free.0, blocked.0);
+        assert_eq!(free.1 - blocked.1, FREE_PATH_EG_BONUS[4]);
+    }
+
+    #[test]
+    fn reuses_the_structure_of_the_same_pawns_and_parameters() {
+        let fen = "4k3/p7/8/8/8/8/1PP5/4K3 w - - 0 1";
+        let params = EvalParams::default();
+        let first = pawn_terms(fen, &params);
+
+        let (board, _) = fen::parse_fen(fen).unwrap();
+        let key = zobrist::hash_pawns(&board) ^ params.pawn_key();
+        let cached =
+            PAWN_TABLE.with(|table| table.borrow()[(key % PAWN_TABLE_SIZE as u64) as usize]);
+        assert_eq!(cached.key, key);
+
+        // The same pawns with other pieces around use the same entry
+        let (moved, _) = fen::parse_fen("3qk3/p7/8/8/8/8/1PP5/3QK3 w - - 0 1").unwrap();
+        assert_eq!(zobrist::hash_pawns(&moved), zobrist::hash_pawns(&board));
+        assert_eq!(pawn_terms(fen, &params), first);
+
+        // Other parameters mustn't be given the cached score
+        let mut harsher = params.clone();
+        harsher.isolated_penalty = (50, 50);
+        harsher.update_pawn_key();
+        assert_ne!(harsher.pawn_key(), params.pawn_key());
+        assert_ne!(pawn_terms(fen, &harsher), first);
+    }
+}
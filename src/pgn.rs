@@ -0,0 +1,209 @@
+use crate::*;
+use fen::parse_fen;
+use notation::parse_move;
+
+// A game read from a PGN file, only its main line is kept
+pub struct PgnGame {
+    // Tag pairs like ("White", "Magnus"), in file order
+    pub tags: Vec<(String, String)>,
+    // The position the game starts from and whether White moves first
+    pub start: Board,
+    pub start_white: bool,
+    pub moves: Vec<Move>,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// Read the first game of a PGN file
+pub fn load(path: &str) -> Result<PgnGame, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
+    parse_pgn(&text)
+}
+
+// Parse the first game in a PGN text, skipping comments, variations and annotations
+pub fn parse_pgn(text: &str) -> Result<PgnGame, String> {
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') && line.ends_with(']') {
+            // A tag after the moves belongs to the next game
+            if !movetext.trim().is_empty() {
+                break;
+            }
+            let (name, value) = line[1..line.len() - 1]
+                .split_once(' ')
+                .ok_or_else(|| format!("Invalid tag: {}", line))?;
+            tags.push((name.to_string(), value.trim().trim_matches('"').to_string()));
+        } else if !line.starts_with('%') {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+
+    let (mut board, mut is_white) = match tags.iter().find(|(name, _)| name == "FEN") {
+        Some((_, fen)) => parse_fen(fen)?,
+        None => (Board::new(), true),
+    };
+    let mut game = PgnGame {
+        tags,
+        start: board,
+        start_white: is_white,
+        moves: Vec::new(),
+    };
+
+    for token in tokens(&movetext) {
+        if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token.as_str()) {
+            break;
+        }
+
+        let move_ = parse_move(board, &token, is_white)
+            .map_err(|err| format!("Move {}: {}", game.moves.len() / 2 + 1, err))?;
+        move_piece(&mut board, move_.0, move_.1, is_white).map_err(|_| {
+            format!(
+                "Move {}: '{}' is not legal",
+                game.moves.len() / 2 + 1,
+                token
+            )
+        })?;
+        game.moves.push(move_);
+        is_white = !is_white;
+    }
+
+    Ok(game)
+}
+
+// The moves and result of the main line, without move numbers
+fn tokens(movetext: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut chars = movetext.chars();
+    let mut variation_depth = 0;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                chars.by_ref().find(|&c| c == '}');
+            }
+            ';' => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '(' => variation_depth += 1,
+            ')' => variation_depth -= 1,
+            _ if variation_depth > 0 => continue,
+            c if c.is_whitespace() => {
+                push_token(&mut tokens, &mut current);
+                continue;
+            }
+            c => {
+                current.push(c);
+                continue;
+            }
+        }
+        push_token(&mut tokens, &mut current);
+    }
+    push_token(&mut tokens, &mut current);
+
+    tokens
+}
+
+fn push_token(tokens: &mut Vec<String>, current: &mut String) {
+    // Move numbers may be written against the move, like "12.e4" or "12...e5"
+    let token = match current.rfind('.') {
+        Some(i) => &current[i + 1..],
+        None => current.as_str(),
+    };
+
+    // Numeric annotation glyphs like "$1" carry no move
+    if !token.is_empty() && !token.starts_with('$') {
+        tokens.push(token.to_string());
+    }
+    current.clear();
+}
+
+// A move as written to PGN, with an optional annotation glyph (e.g. 2 for "?") and comment
+pub struct PgnMove {
+    pub move_: Move,
+    pub nag: Option<u8>,
+    pub comment: Option<String>,
+}
+
+// Write a game as PGN, adding the FEN tags when it doesn't start from the starting position
+pub fn write_pgn(
+    tags: &[(&str, String)],
+    start: Board,
+    start_white: bool,
+    moves: &[PgnMove],
+    result: &str,
+) -> String {
+    let mut text = String::new();
+    for (name, value) in tags {
+        text.push_str(&format!("[{} \"{}\"]\n", name, value.replace('"', "'")));
+    }
+    text.push_str(&format!("[Result \"{}\"]\n", result));
+    let fen = fen::to_fen(&start, start_white);
+    if fen != fen::to_fen(&Board::new(), true) {
+        text.push_str(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", fen));
+    }
+    text.push('\n');
+
+    let (mut board, mut is_white) = (start, start_white);
+    let mut number = 1;
+    let mut words = Vec::new();
+    for (i, pgn_move) in moves.iter().enumerate() {
+        if is_white {
+            words.push(format!("{}.", number));
+        } else if i == 0 {
+            words.push(format!("{}...", number));
+        }
+        words.push(notation::move_to_san(board, pgn_move.move_, is_white));
+        if let Some(nag) = pgn_move.nag {
+            words.push(format!("${}", nag));
+        }
+        if let Some(comment) = &pgn_move.comment {
+            let comment = format!("{{{}}}", comment.replace('}', ")"));
+            words.extend(comment.split_whitespace().map(str::to_string));
+            // Black's move needs its number again after a comment on White's move
+            if is_white && i + 1 < moves.len() {
+                words.push(format!("{}...", number));
+            }
+        }
+
+        let (from, to) = pgn_move.move_;
+        if move_piece(&mut board, from, to, is_white).is_err() {
+            break;
+        }
+        if !is_white {
+            number += 1;
+        }
+        is_white = !is_white;
+    }
+    words.push(result.to_string());
+
+    // Movetext lines are kept below 80 characters
+    let mut line = String::new();
+    for word in words {
+        if !line.is_empty() && line.len() + word.len() + 1 > 79 {
+            text.push_str(&line);
+            text.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    text.push_str(&line);
+    text.push('\n');
+
+    text
+}
@@ -0,0 +1,238 @@
+use crate::*;
+use evaluation::{EvalTrace, Term};
+use params::EvalParams;
+
+// Default bonus per reachable tile above (or penalty below) a typical number of moves, as (middlegame, endgame)
+pub const KNIGHT_MOBILITY: (i32, i32) = (4, 4);
+pub const BISHOP_MOBILITY: (i32, i32) = (5, 5);
+pub const ROOK_MOBILITY: (i32, i32) = (2, 4);
+pub const QUEEN_MOBILITY: (i32, i32) = (1, 2);
+pub const KNIGHT_TYPICAL_MOVES: i32 = 4;
+pub const BISHOP_TYPICAL_MOVES: i32 = 7;
+pub const ROOK_TYPICAL_MOVES: i32 = 7;
+pub const QUEEN_TYPICAL_MOVES: i32 = 14;
+
+pub const BISHOP_PAIR_BONUS: (i32, i32) = (30, 50);
+pub const ROOK_OPEN_FILE_BONUS: (i32, i32) = (20, 10);
+pub const ROOK_SEMI_OPEN_FILE_BONUS: (i32, i32) = (10, 5);
+pub const ROOK_SEVENTH_RANK_BONUS: (i32, i32) = (20, 30);
+
+// King safety only matters while there are pieces around to attack, so it is middlegame only
+// Bonus for pawns one and two tiles in front of the king
+pub const PAWN_SHIELD_BONUS: [i32; 2] = [10, 5];
+// Penalty for files next to the king without own pawns, and extra if the enemy has none either
+pub const KING_SEMI_OPEN_FILE_PENALTY: i32 = 15;
+pub const KING_OPEN_FILE_PENALTY: i32 = 10;
+// Weight of a piece attacking the tiles around the king
+pub const KNIGHT_ATTACK_WEIGHT: i32 = 2;
+pub const BISHOP_ATTACK_WEIGHT: i32 = 2;
+pub const ROOK_ATTACK_WEIGHT: i32 = 3;
+pub const QUEEN_ATTACK_WEIGHT: i32 = 5;
+// Bonus for the attacking side by total attack weight, growing faster the more pieces join in
+pub const KING_ATTACK_BONUS: [i32; 16] = [
+    0, 0, 5, 10, 20, 30, 45, 60, 80, 100, 125, 150, 180, 210, 250, 300,
+];
+
+// Evaluate piece mobility, king safety, the bishop pair and rook placement
+pub fn evaluate_pieces(board: &Board, params: &EvalParams, trace: &mut EvalTrace) {
+    evaluate_side(board, true, params, trace);
+    evaluate_side(board, false, params, trace);
+}
+
+fn evaluate_side(board: &Board, is_white: bool, params: &EvalParams, trace: &mut EvalTrace) {
+    let colour = if is_white {
+        Colour::White
+    } else {
+        Colour::Black
+    };
+    let enemy_king = if is_white {
+        board.kingpos_b
+    } else {
+        board.kingpos_w
+    };
+    let mut bishops = 0;
+    let mut attack_weight = 0;
+    let mut attackers = 0;
+
+    for row in 0..8 {
+        for col in 0..8 {
+            let piece = board.tiles[row][col].piece;
+            if piece.colour != colour {
+                continue;
+            }
+
+            let (moves, mobility, typical_moves, attack) = match piece.piece_type {
+                Type::Knight => (
+                    legal_knight_moves(*board, (row, col), is_white),
+                    params.knight_mobility,
+                    params.knight_typical_moves,
+                    params.knight_attack_weight,
+                ),
+                Type::Bishop => {
+                    bishops += 1;
+                    (
+                        legal_diagonal_moves(*board, (row, col), is_white),
+                        params.bishop_mobility,
+                        params.bishop_typical_moves,
+                        params.bishop_attack_weight,
+                    )
+                }
+                Type::Rook(_) => {
+                    let (rook_mg, rook_eg) = rook_placement(board, (row, col), is_white, params);
+                    trace.add(Term::Pieces, is_white, rook_mg, rook_eg);
+                    (
+                        legal_straight_moves(*board, (row, col), is_white),
+                        params.rook_mobility,
+                        params.rook_typical_moves,
+                        params.rook_attack_weight,
+                    )
+                }
+                Type::Queen => {
+                    let mut moves = legal_straight_moves(*board, (row, col), is_white);
+                    moves.append(&mut legal_diagonal_moves(*board, (row, col), is_white));
+                    (
+                        moves,
+                        params.queen_mobility,
+                        params.queen_typical_moves,
+                        params.queen_attack_weight,
+                    )
+                }
+                _ => continue,
+            };
+
+            let extra_moves = moves.len() as i32 - typical_moves;
+            trace.add(
+                Term::Mobility,
+                is_white,
+                mobility.0 * extra_moves,
+                mobility.1 * extra_moves,
+            );
+
+            // Does the piece reach the enemy king or the tiles around it
+            if moves
+                .iter()
+                .any(|&(r, c)| r.abs_diff(enemy_king.0) <= 1 && c.abs_diff(enemy_king.1) <= 1)
+            {
+                attackers += 1;
+                attack_weight += attack;
+            }
+        }
+    }
+
+    if bishops >= 2 {
+        trace.add(
+            Term::Pieces,
+            is_white,
+            params.bishop_pair_bonus.0,
+            params.bishop_pair_bonus.1,
+        );
+    }
+
+    // A single attacker is rarely dangerous, the bonus goes to the attacking side
+    if attackers >= 2 {
+        let bonus = params.king_attack_bonus
+            [(attack_weight as usize).min(params.king_attack_bonus.len() - 1)];
+        trace.add(Term::KingSafety, is_white, bonus, 0);
+    }
+
+    trace.add(
+        Term::KingSafety,
+        is_white,
+        king_shelter(board, is_white, params),
+        0,
+    );
+}
+
+fn has_pawn_on_file(board: &Board, col: usize, colour: Colour) -> bool {
+    (0..8).any(|row| {
+        let piece = board.tiles[row][col].piece;
+        matches!(piece.piece_type, Type::Pawn(_)) && piece.colour == colour
+    })
+}
+
+// Bonuses for a rook on an open or semi-open file and on the 7th rank
+fn rook_placement(
+    board: &Board,
+    pos: (usize, usize),
+    is_white: bool,
+    params: &EvalParams,
+) -> (i32, i32) {
+    let (own, enemy) = if is_white {
+        (Colour::White, Colour::Black)
+    } else {
+        (Colour::Black, Colour::White)
+    };
+    let mut mg = 0;
+    let mut eg = 0;
+
+    if !has_pawn_on_file(board, pos.1, own) {
+        if !has_pawn_on_file(board, pos.1, enemy) {
+            mg += params.rook_open_file_bonus.0;
+            eg += params.rook_open_file_bonus.1;
+        } else {
+            mg += params.rook_semi_open_file_bonus.0;
+            eg += params.rook_semi_open_file_bonus.1;
+        }
+    }
+
+    // The 7th rank only counts if there are enemy pawns on it or the enemy king is stuck behind it
+    let (seventh, eighth) = if is_white { (1, 0) } else { (6, 7) };
+    let enemy_king = if is_white {
+        board.kingpos_b
+    } else {
+        board.kingpos_w
+    };
+    if pos.0 == seventh {
+        let enemy_pawns_on_seventh = board.tiles[seventh].iter().any(|tile| {
+            matches!(tile.piece.piece_type, Type::Pawn(_)) && tile.piece.colour == enemy
+        });
+
+        if enemy_pawns_on_seventh || enemy_king.0 == eighth {
+            mg += params.rook_seventh_rank_bonus.0;
+            eg += params.rook_seventh_rank_bonus.1;
+        }
+    }
+
+    (mg, eg)
+}
+
+// Middlegame score for the pawns in front of the king and the open files around it
+fn king_shelter(board: &Board, is_white: bool, params: &EvalParams) -> i32 {
+    let (own, enemy) = if is_white {
+        (Colour::White, Colour::Black)
+    } else {
+        (Colour::Black, Colour::White)
+    };
+    let king = if is_white {
+        board.kingpos_w
+    } else {
+        board.kingpos_b
+    };
+    let mut score = 0;
+
+    for col in king.1.saturating_sub(1)..=(king.1 + 1).min(7) {
+        for (distance, bonus) in params.pawn_shield_bonus.iter().enumerate() {
+            let row = if is_white {
+                king.0.checked_sub(distance + 1)
+            } else {
+                Some(king.0 + distance + 1).filter(|&row| row < 8)
+            };
+
+            if let Some(row) = row {
+                let piece = board.tiles[row][col].piece;
+                if matches!(piece.piece_type, Type::Pawn(_)) && piece.colour == own {
+                    score += bonus;
+                }
+            }
+        }
+
+        if !has_pawn_on_file(board, col, own) {
+            score -= params.king_semi_open_file_penalty;
+            if !has_pawn_on_file(board, col, enemy) {
+                score -= params.king_open_file_penalty;
+            }
+        }
+    }
+
+    score
+}
@@ -0,0 +1,76 @@
+use crate::*;
+
+// The moves of a game as they are played, kept to review and save the game afterwards
+pub struct GameRecord {
+    pub start: Board,
+    pub start_white: bool,
+    pub moves: Vec<RecordedMove>,
+}
+
+pub struct RecordedMove {
+    pub move_: Move,
+    // Hints the player asked for before making this move
+    pub hints: u32,
+}
+
+impl GameRecord {
+    pub fn new(start: Board, start_white: bool) -> Self {
+        GameRecord {
+            start,
+            start_white,
+            moves: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, move_: Move, hints: u32) {
+        self.moves.push(RecordedMove { move_, hints });
+    }
+
+    // Whether the given side made the move with this index
+    pub fn is_white_move(&self, index: usize) -> bool {
+        index.is_multiple_of(2) == self.start_white
+    }
+
+    // The moves one side asked hints for, in SAN with their move numbers
+    pub fn hinted_moves(&self, is_white: bool) -> Vec<String> {
+        let mut board = self.start;
+        let mut hinted = Vec::new();
+
+        for (i, recorded) in self.moves.iter().enumerate() {
+            let mover = self.is_white_move(i);
+            if mover == is_white && recorded.hints > 0 {
+                hinted.push(format!(
+                    "{}{} {}",
+                    self.move_number(i),
+                    if mover { "." } else { "..." },
+                    notation::move_to_san(board, recorded.move_, mover)
+                ));
+            }
+            let (from, to) = recorded.move_;
+            if move_piece(&mut board, from, to, mover).is_err() {
+                break;
+            }
+        }
+
+        hinted
+    }
+
+    // Total hints used by one side
+    pub fn hints(&self, is_white: bool) -> u32 {
+        self.moves
+            .iter()
+            .enumerate()
+            .filter(|&(i, _)| self.is_white_move(i) == is_white)
+            .map(|(_, recorded)| recorded.hints)
+            .sum()
+    }
+
+    // The number of the full move the move with this index belongs to
+    pub fn move_number(&self, index: usize) -> usize {
+        if self.start_white {
+            index / 2 + 1
+        } else {
+            index.div_ceil(2) + 1
+        }
+    }
+}
@@ -0,0 +1,271 @@
+use crate::*;
+use ansi_term::Colour::{Red, White};
+use engine::{format_score, Engine};
+use evaluation::MATE_SCORE;
+use input::read_line;
+use pgn::{write_pgn, PgnMove};
+use record::GameRecord;
+use settings::Settings;
+use std::io::Write;
+
+// Depth every position of the game is searched to
+const REVIEW_DEPTH: i32 = 4;
+// Scores are capped before comparing them, so choosing a slower mate or winning
+// a little less in a won position isn't counted as a mistake
+const SCORE_CAP: i32 = 1000;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Judgement {
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+// Judgements from the worst down, with the evaluation drop in centipawns they start at
+const JUDGEMENTS: [(Judgement, i32); 3] = [
+    (Judgement::Blunder, 300),
+    (Judgement::Mistake, 100),
+    (Judgement::Inaccuracy, 50),
+];
+
+impl Judgement {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "Inaccuracy",
+            Judgement::Mistake => "Mistake",
+            Judgement::Blunder => "Blunder",
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Judgement::Inaccuracy => "?!",
+            Judgement::Mistake => "?",
+            Judgement::Blunder => "??",
+        }
+    }
+
+    // Numeric annotation glyph used in PGN
+    pub fn nag(&self) -> u8 {
+        match self {
+            Judgement::Inaccuracy => 6,
+            Judgement::Mistake => 2,
+            Judgement::Blunder => 4,
+        }
+    }
+}
+
+// What Magnus thinks of one move of the game, scores are from White's point of view
+pub struct MoveReview {
+    pub judgement: Option<Judgement>,
+    // Evaluation before and after the move
+    pub before: i32,
+    pub after: i32,
+    // Magnus' line from the position before the move
+    pub best_line: Vec<Move>,
+}
+
+// Search every position of the game and judge each move by how much it dropped the evaluation
+pub fn review_game(record: &GameRecord, engine: &Engine) -> Vec<MoveReview> {
+    let mut board = record.start;
+    let mut evaluations = Vec::new();
+
+    for i in 0..=record.moves.len() {
+        let is_white = record.is_white_move(i);
+        print!(
+            "\r{} Reviewing position {} of {}...",
+            Red.bold().paint(">>>"),
+            i + 1,
+            record.moves.len() + 1
+        );
+        std::io::stdout().flush().unwrap();
+
+        let evaluation = match check_for_mates(board) {
+            Some(Colour::White) => (MATE_SCORE, Vec::new()),
+            Some(Colour::Black) => (-MATE_SCORE, Vec::new()),
+            None => {
+                let result = engine.start_search(board, REVIEW_DEPTH, is_white).wait();
+                let score = if is_white {
+                    result.score
+                } else {
+                    -result.score
+                };
+                (score, result.pv)
+            }
+        };
+        evaluations.push(evaluation);
+
+        if let Some(recorded) = record.moves.get(i) {
+            let (from, to) = recorded.move_;
+            if move_piece(&mut board, from, to, is_white).is_err() {
+                break;
+            }
+        }
+    }
+    println!();
+
+    record
+        .moves
+        .iter()
+        .zip(evaluations.windows(2))
+        .enumerate()
+        .map(|(i, (recorded, pair))| {
+            let ((before, best_line), (after, _)) = (&pair[0], &pair[1]);
+            let (before, after) = (*before, *after);
+            let sign = if record.is_white_move(i) { 1 } else { -1 };
+            let drop =
+                sign * (before.clamp(-SCORE_CAP, SCORE_CAP) - after.clamp(-SCORE_CAP, SCORE_CAP));
+
+            let judgement = if best_line.first() == Some(&recorded.move_) {
+                None
+            } else {
+                JUDGEMENTS
+                    .iter()
+                    .find(|&&(_, threshold)| drop >= threshold)
+                    .map(|&(judgement, _)| judgement)
+            };
+
+            MoveReview {
+                judgement,
+                before,
+                after,
+                best_line: best_line.clone(),
+            }
+        })
+        .collect()
+}
+
+// Ask whether to review a finished game, then show the annotated moves and offer to save
+// them as PGN
+pub fn offer_review(record: &GameRecord, settings: &Settings, players: [&str; 2], result: &str) {
+    if record.moves.is_empty() {
+        return;
+    }
+
+    arrow_print("Do you want Magnus to review the game? (y/n)", true);
+    if !prompt().eq_ignore_ascii_case("y") {
+        return;
+    }
+
+    let reviews = review_game(record, &settings.engine());
+    print_review(record, &reviews);
+
+    arrow_print(
+        "Enter a file name to save the annotated game as PGN, or press Enter to skip:",
+        true,
+    );
+    let path = prompt();
+    if path.is_empty() {
+        return;
+    }
+
+    let text = annotated_pgn(record, &reviews, players, result);
+    match std::fs::write(&path, text) {
+        Ok(_) => arrow_print(&format!("Saved the game to {}", path), true),
+        Err(err) => arrow_print(&format!("Could not write {}: {}", path, err), true),
+    }
+}
+
+// List the moves that were judged and how many of each kind both sides made
+fn print_review(record: &GameRecord, reviews: &[MoveReview]) {
+    let mut board = record.start;
+    let mut counts = [[0; 3]; 2];
+
+    for (i, (recorded, review)) in record.moves.iter().zip(reviews).enumerate() {
+        let is_white = record.is_white_move(i);
+        if let Some(judgement) = review.judgement {
+            let kind = JUDGEMENTS
+                .iter()
+                .position(|&(j, _)| j == judgement)
+                .unwrap();
+            counts[!is_white as usize][kind] += 1;
+
+            println!(
+                "{} {}{} {}{} {}: {} -> {}, better was {} ({})",
+                Red.bold().paint(">>>"),
+                record.move_number(i),
+                if is_white { "." } else { "..." },
+                notation::move_to_san(board, recorded.move_, is_white),
+                judgement.symbol(),
+                White.bold().paint(judgement.name()),
+                format_score(review.before),
+                format_score(review.after),
+                notation::line_to_san(board, &review.best_line, is_white),
+                format_score(review.before)
+            );
+        }
+
+        let (from, to) = recorded.move_;
+        if move_piece(&mut board, from, to, is_white).is_err() {
+            break;
+        }
+    }
+
+    for (side, counts) in ["White", "Black"].iter().zip(counts) {
+        println!(
+            "{} {}: {} blunders, {} mistakes, {} inaccuracies",
+            Red.bold().paint(">>>"),
+            side,
+            counts[0],
+            counts[1],
+            counts[2]
+        );
+    }
+}
+
+// The game as PGN with the judgements as annotation glyphs and the better lines as comments
+fn annotated_pgn(
+    record: &GameRecord,
+    reviews: &[MoveReview],
+    players: [&str; 2],
+    result: &str,
+) -> String {
+    let mut board = record.start;
+    let mut moves = Vec::new();
+
+    for (i, (recorded, review)) in record.moves.iter().zip(reviews).enumerate() {
+        let is_white = record.is_white_move(i);
+        let mut comments = Vec::new();
+        if let Some(judgement) = review.judgement {
+            comments.push(format!(
+                "{}, the evaluation went from {} to {}. {} was better.",
+                judgement.name(),
+                format_score(review.before),
+                format_score(review.after),
+                notation::line_to_san(board, &review.best_line, is_white)
+            ));
+        }
+        if recorded.hints > 0 {
+            comments.push(format!("Played after {} hint(s).", recorded.hints));
+        }
+
+        moves.push(PgnMove {
+            move_: recorded.move_,
+            nag: review.judgement.map(|judgement| judgement.nag()),
+            comment: (!comments.is_empty()).then(|| comments.join(" ")),
+        });
+
+        let (from, to) = recorded.move_;
+        if move_piece(&mut board, from, to, is_white).is_err() {
+            break;
+        }
+    }
+
+    let tags = [
+        ("Event", "C-Chess game".to_string()),
+        ("Site", "?".to_string()),
+        ("Date", "????.??.??".to_string()),
+        ("Round", "-".to_string()),
+        ("White", players[0].to_string()),
+        ("Black", players[1].to_string()),
+    ];
+    write_pgn(&tags, record.start, record.start_white, &moves, result)
+}
+
+fn prompt() -> String {
+    print!("{} ", White.bold().paint(">>>"));
+    std::io::stdout().flush().unwrap();
+    let mut input = String::new();
+    read_line(&mut input);
+    input.trim().to_string()
+}
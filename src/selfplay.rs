@@ -0,0 +1,472 @@
+use crate::*;
+use ansi_term::Colour::{Red, White};
+use book::Book;
+use clock::{can_mate, Clock, TimeControl};
+use engine::{Engine, SearchHandle, SearchResult};
+use notation::{legal_move_list, move_to_san, parse_move};
+use params::EvalParams;
+use record::GameRecord;
+use settings::Settings;
+use std::io::Write;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use zobrist::hash_board;
+
+// Games still going after this many moves by both sides are drawn
+const MAX_PLIES: usize = 400;
+// Moves per side played at random for a random opening
+const RANDOM_OPENING_MOVES: usize = 3;
+// How long each move stays on the screen at least when watching, unless chosen otherwise
+const DEFAULT_PACE_MS: u64 = 500;
+
+// One side of an engine game and how it searches
+#[derive(Clone)]
+pub struct Player {
+    pub name: String,
+    pub engine: Engine,
+    pub depth: i32,
+    // Stop searching after this long, even when the depth isn't reached yet
+    pub move_time: Option<Duration>,
+    pub book: Option<Arc<Book>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Ending {
+    Checkmate,
+    Stalemate,
+    Repetition,
+    InsufficientMaterial,
+    MoveLimit,
+    Time,
+    // The engine came up with a move that can't be played
+    IllegalMove,
+}
+
+impl Ending {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Ending::Checkmate => "checkmate",
+            Ending::Stalemate => "stalemate",
+            Ending::Repetition => "threefold repetition",
+            Ending::InsufficientMaterial => "insufficient material",
+            Ending::MoveLimit => "the move limit",
+            Ending::Time => "time",
+            Ending::IllegalMove => "an illegal move",
+        }
+    }
+}
+
+pub struct GameResult {
+    pub winner: Option<Colour>,
+    pub ending: Ending,
+    pub record: GameRecord,
+    // Time spent thinking, moves searched and moves taken from the book, White first
+    pub thinking: [Duration; 2],
+    pub searched: [usize; 2],
+    pub book_moves: [usize; 2],
+    // Evaluation of the last search from White's point of view
+    pub last_score: Option<i32>,
+}
+
+// Play a game between two players from a position, starting with the given opening moves
+// When watching, the board is drawn after every move, which stays on the screen for at least
+// the given time
+pub fn play_game(
+    players: [&Player; 2],
+    start: (Board, bool),
+    opening: &[Move],
+    time_control: Option<TimeControl>,
+    watch: Option<Duration>,
+) -> GameResult {
+    let (mut board, mut is_white) = start;
+    let mut record = GameRecord::new(board, is_white);
+    for &(from, to) in opening {
+        if move_piece(&mut board, from, to, is_white).is_err() {
+            break;
+        }
+        record.push((from, to), 0);
+        is_white = !is_white;
+    }
+
+    let mut clock = time_control.map(Clock::new);
+    let mut positions = vec![hash_board(&board, is_white)];
+    let mut thinking = [Duration::ZERO; 2];
+    let mut searched = [0; 2];
+    let mut book_moves = [0; 2];
+    let mut last_score = None;
+    if let Some(pace) = watch {
+        clear_draw(board, true);
+        std::thread::sleep(pace);
+    }
+
+    let (winner, ending) = loop {
+        if let Some(winner) = check_for_mates(board) {
+            break (Some(winner), Ending::Checkmate);
+        }
+        if legal_move_list(board, is_white).is_empty() {
+            break (None, Ending::Stalemate);
+        }
+        if !can_mate(&board, true) && !can_mate(&board, false) {
+            break (None, Ending::InsufficientMaterial);
+        }
+        let hash = positions[positions.len() - 1];
+        if positions.iter().filter(|&&seen| seen == hash).count() >= 3 {
+            break (None, Ending::Repetition);
+        }
+        if record.moves.len() >= MAX_PLIES {
+            break (None, Ending::MoveLimit);
+        }
+
+        let side = !is_white as usize;
+        let player = players[side];
+        let move_start = Instant::now();
+        if let Some(clock) = clock.as_mut() {
+            clock.start(is_white);
+        }
+        let budget = match (clock.as_ref(), player.move_time) {
+            (Some(clock), Some(time)) => Some(clock.budget(is_white).min(time)),
+            (Some(clock), None) => Some(clock.budget(is_white)),
+            (None, time) => time,
+        };
+
+        let last_move = record.moves.last().map(|recorded| recorded.move_);
+        let book_move = player
+            .book
+            .as_ref()
+            .and_then(|book| book.pick_move(board, is_white, last_move));
+        let (move_, result) = match book_move {
+            Some(move_) => {
+                book_moves[side] += 1;
+                (move_, None)
+            }
+            None => {
+                searched[side] += 1;
+                let handle = player.engine.start_search(board, player.depth, is_white);
+                let result = match watch {
+                    Some(_) => wait_for_search_timed(handle, budget),
+                    None => wait_silently(handle, budget),
+                };
+                (result.best_move, Some(result))
+            }
+        };
+        thinking[side] += move_start.elapsed();
+
+        if clock.as_mut().is_some_and(|clock| !clock.stop()) {
+            let opponent = if is_white {
+                Colour::Black
+            } else {
+                Colour::White
+            };
+            break (
+                can_mate(&board, !is_white).then_some(opponent),
+                Ending::Time,
+            );
+        }
+
+        let before = board;
+        if move_piece(&mut board, move_.0, move_.1, is_white).is_err() {
+            let opponent = if is_white {
+                Colour::Black
+            } else {
+                Colour::White
+            };
+            break (Some(opponent), Ending::IllegalMove);
+        }
+        record.push(move_, 0);
+        if let Some(result) = &result {
+            last_score = Some(if is_white {
+                result.score
+            } else {
+                -result.score
+            });
+        }
+
+        if let Some(pace) = watch {
+            clear_draw(board, true);
+            println!(
+                "{} {} ({}) played {} after {:.1?}",
+                Red.bold().paint(">>>"),
+                if is_white { "White" } else { "Black" },
+                player.name,
+                Red.bold().paint(move_to_san(before, move_, is_white)),
+                move_start.elapsed()
+            );
+            print_search_info(before, result.as_ref(), is_white);
+            if let Some(clock) = &clock {
+                println!("{} {}", Red.bold().paint(">>>"), clock.display());
+            }
+            std::thread::sleep(pace.saturating_sub(move_start.elapsed()));
+        }
+
+        is_white = !is_white;
+        positions.push(hash_board(&board, is_white));
+    };
+
+    GameResult {
+        winner,
+        ending,
+        record,
+        thinking,
+        searched,
+        book_moves,
+        last_score,
+    }
+}
+
+// Wait for a search without reading any input, stopping it once its time is used up
+fn wait_silently(handle: SearchHandle, budget: Option<Duration>) -> SearchResult {
+    let start = Instant::now();
+    while !handle.is_finished() {
+        if budget.is_some_and(|budget| start.elapsed() >= budget) {
+            handle.stop();
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+    handle.wait()
+}
+
+// Moves played at random by both sides, to start engine games from different positions
+pub fn random_opening(mut board: Board, mut is_white: bool, plies: usize) -> Vec<Move> {
+    let mut opening = Vec::new();
+    for _ in 0..plies {
+        let moves = legal_move_list(board, is_white);
+        if moves.is_empty() {
+            break;
+        }
+        let (from, to) = moves[fastrand::usize(..moves.len())];
+        if move_piece(&mut board, from, to, is_white).is_err() {
+            break;
+        }
+        opening.push((from, to));
+        is_white = !is_white;
+    }
+    opening
+}
+
+// Let two differently configured Magnus play each other while the user watches
+pub fn watch_game(settings: &Settings) {
+    clear_screen();
+    let white = ask_player(settings, "White");
+    let black = ask_player(settings, "Black");
+    let (start, opening) = ask_opening();
+
+    arrow_print(
+        &format!(
+            "How many milliseconds should each move stay on the screen at least? Leave empty for {}.",
+            DEFAULT_PACE_MS
+        ),
+        true,
+    );
+    let pace = loop {
+        let text = prompt();
+        if text.is_empty() {
+            break Duration::from_millis(DEFAULT_PACE_MS);
+        }
+        match text.parse::<u64>() {
+            Ok(ms) => break Duration::from_millis(ms),
+            Err(_) => arrow_print("Invalid input!", true),
+        }
+    };
+
+    let result = play_game(
+        [&white, &black],
+        start,
+        &opening,
+        settings.time_control,
+        Some(pace),
+    );
+    print_summary(&result, [&white, &black]);
+
+    review::offer_review(
+        &result.record,
+        settings,
+        [&white.name, &black.name],
+        pgn_result(result.winner),
+    );
+    arrow_print("Press Enter to exit.", true);
+    prompt();
+}
+
+// Ask how one side should play: its skill level, how deep or long it searches and which
+// evaluation weights it uses
+fn ask_player(settings: &Settings, side: &str) -> Player {
+    clear_screen();
+    arrow_print(&format!("Setting up {}.", side), true);
+    let skill = ask_skill();
+    let mut engine = settings.engine();
+    engine.skill = Some(skill);
+    let mut name = if skill.is_full_strength() {
+        "Magnus".to_string()
+    } else {
+        format!("Magnus level {}", skill.level())
+    };
+
+    arrow_print(
+        &format!("How should {} search? Enter a depth like 6, a time per move like 2s, or leave empty for the default.", side),
+        true,
+    );
+    let (depth, move_time) = loop {
+        let text = prompt();
+        if text.is_empty() {
+            break (search_depth(skill, settings.time_control.is_some()), None);
+        }
+        if let Some(seconds) = text.strip_suffix('s') {
+            match seconds.trim().parse::<f64>() {
+                Ok(seconds) if seconds > 0.0 => {
+                    name.push_str(&format!(", {}s per move", seconds));
+                    break (TIMED_DEPTH, Some(Duration::from_secs_f64(seconds)));
+                }
+                _ => arrow_print("Invalid input!", true),
+            }
+        } else {
+            match text.parse::<i32>() {
+                Ok(depth) if depth > 0 => {
+                    name.push_str(&format!(", depth {}", depth));
+                    break (depth, None);
+                }
+                _ => arrow_print("Invalid input!", true),
+            }
+        }
+    };
+
+    arrow_print(
+        &format!(
+            "Which evaluation parameter file should {} use? Leave empty for the current evaluation.",
+            side
+        ),
+        true,
+    );
+    loop {
+        let path = prompt();
+        if path.is_empty() {
+            break;
+        }
+        match EvalParams::load(&path) {
+            Ok(params) => {
+                engine.params = Arc::new(params);
+                engine.network = None;
+                name.push_str(&format!(", {}", path));
+                break;
+            }
+            Err(err) => arrow_print(&err, true),
+        }
+    }
+
+    Player {
+        name,
+        engine,
+        depth,
+        move_time,
+        book: settings.book.clone(),
+    }
+}
+
+// Ask where the game starts, returns the position and the opening moves played from it
+fn ask_opening() -> ((Board, bool), Vec<Move>) {
+    clear_screen();
+    arrow_print("How should the game start?", true);
+    arrow_print(
+        "(1) Starting position\n(2) A few random moves\n(3) From a FEN\n(4) From a list of moves, like e4 e5 Nf3\n",
+        false,
+    );
+
+    let start = (Board::new(), true);
+    loop {
+        match prompt().as_str() {
+            "1" => return (start, Vec::new()),
+            "2" => {
+                return (
+                    start,
+                    random_opening(start.0, start.1, 2 * RANDOM_OPENING_MOVES),
+                )
+            }
+            "3" => {
+                arrow_print("Enter the FEN:", true);
+                match fen::parse_fen(&prompt()) {
+                    Ok(start) => return (start, Vec::new()),
+                    Err(err) => arrow_print(&err, true),
+                }
+            }
+            "4" => {
+                arrow_print("Enter the moves:", true);
+                match parse_opening(start, &prompt()) {
+                    Ok(opening) => return (start, opening),
+                    Err(err) => arrow_print(&err, true),
+                }
+            }
+            _ => arrow_print("Invalid input!", true),
+        }
+    }
+}
+
+// Read a list of moves in SAN or coordinates, skipping move numbers
+fn parse_opening(
+    (mut board, mut is_white): (Board, bool),
+    text: &str,
+) -> Result<Vec<Move>, String> {
+    let mut opening = Vec::new();
+    for word in text.split_whitespace() {
+        let word = word.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        if word.is_empty() {
+            continue;
+        }
+        let (from, to) = parse_move(board, word, is_white)?;
+        move_piece(&mut board, from, to, is_white)
+            .map_err(|_| format!("{} can't be played", word))?;
+        opening.push((from, to));
+        is_white = !is_white;
+    }
+    Ok(opening)
+}
+
+// The result of the game and how each side used its time
+fn print_summary(result: &GameResult, players: [&Player; 2]) {
+    let moves = result.record.moves.len();
+    let verdict = match result.winner {
+        Some(winner) => format!("{} wins by {}", winner.ctos(), result.ending.name()),
+        None => format!("Draw by {}", result.ending.name()),
+    };
+    arrow_print(
+        &format!(
+            "{} {} after {} moves",
+            pgn_result(result.winner),
+            verdict,
+            result.record.move_number(moves.saturating_sub(1))
+        ),
+        true,
+    );
+    if let Some(score) = result.last_score {
+        arrow_print(
+            &format!("Last evaluation: {}", engine::format_score(score)),
+            false,
+        );
+    }
+
+    for (i, (side, player)) in ["White", "Black"].iter().zip(players).enumerate() {
+        let played = (0..moves)
+            .filter(|&index| result.record.is_white_move(index) == (i == 0))
+            .count();
+        println!(
+            "{} {} ({}): {} moves, {} searched and {} from the book, {:.1?} thinking, {:.1?} per search",
+            White.bold().paint(">>>"),
+            side,
+            player.name,
+            played,
+            result.searched[i],
+            result.book_moves[i],
+            result.thinking[i],
+            result.thinking[i] / result.searched[i].max(1) as u32
+        );
+    }
+}
+
+fn prompt() -> String {
+    print!("{} ", White.bold().paint(">>>"));
+    std::io::stdout().flush().unwrap();
+    let mut input = String::new();
+    read_line(&mut input);
+    if input.trim() == "exit" {
+        std::process::exit(0);
+    }
+    input.trim().to_string()
+}
@@ -0,0 +1,298 @@
+use crate::*;
+use book::Book;
+use clock::TimeControl;
+use engine::Engine;
+use nnue::Network;
+use params::EvalParams;
+use std::sync::Arc;
+
+// Options that apply to every game, set from the command line or the settings menu
+pub struct Settings {
+    // Number of threads Magnus searches with
+    pub threads: usize,
+    // Let Magnus think on the expected reply during the player's turn
+    pub ponder: bool,
+    // Weights of Magnus' evaluation, and the file they were loaded from
+    pub eval_params: Arc<EvalParams>,
+    pub eval_params_file: Option<String>,
+    // Neural network Magnus evaluates with instead, and the file it was loaded from
+    pub network: Option<Arc<Network>>,
+    pub network_file: Option<String>,
+    // Polyglot opening book Magnus plays from, and the file it was loaded from
+    pub book: Option<Arc<Book>>,
+    pub book_file: Option<String>,
+    // Clocks for every game, None to play without them
+    pub time_control: Option<TimeControl>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            threads: 1,
+            ponder: false,
+            eval_params: Arc::new(EvalParams::default()),
+            eval_params_file: None,
+            network: None,
+            network_file: None,
+            book: None,
+            book_file: None,
+            time_control: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn from_args() -> Self {
+        let mut settings = Settings::default();
+        let mut args = std::env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-t" | "--threads" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                    Some(n) if n > 0 => settings.threads = n,
+                    _ => {
+                        eprintln!("{} expects a positive number of threads", arg);
+                        std::process::exit(1);
+                    }
+                },
+                "-p" | "--ponder" => settings.ponder = true,
+                "-e" | "--eval-params" => {
+                    let path = expect_path(&arg, args.next());
+                    if let Err(err) = settings.load_eval_params(&path) {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                }
+                "-n" | "--nnue" => {
+                    let path = expect_path(&arg, args.next());
+                    if let Err(err) = settings.load_network(&path) {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                }
+                "-b" | "--book" => {
+                    let path = expect_path(&arg, args.next());
+                    if let Err(err) = settings.load_book(&path) {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                }
+                "-c" | "--clock" => match args.next().map(|text| TimeControl::parse(&text)) {
+                    Some(Ok(control)) => settings.time_control = Some(control),
+                    Some(Err(err)) => {
+                        eprintln!("{}", err);
+                        std::process::exit(1);
+                    }
+                    None => {
+                        eprintln!("{} expects a time control like 5+3", arg);
+                        std::process::exit(1);
+                    }
+                },
+                "--save-eval-params" => {
+                    let path = expect_path(&arg, args.next());
+                    match settings.eval_params.save(&path) {
+                        Ok(()) => std::process::exit(0),
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                "-h" | "--help" => {
+                    print_usage();
+                    std::process::exit(0);
+                }
+                _ => {
+                    eprintln!("Unknown argument: {}", arg);
+                    print_usage();
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        settings
+    }
+
+    pub fn load_eval_params(&mut self, path: &str) -> Result<(), String> {
+        self.eval_params = Arc::new(EvalParams::load(path)?);
+        self.eval_params_file = Some(path.to_string());
+        Ok(())
+    }
+
+    pub fn load_network(&mut self, path: &str) -> Result<(), String> {
+        self.network = Some(Arc::new(Network::load(path)?));
+        self.network_file = Some(path.to_string());
+        Ok(())
+    }
+
+    pub fn load_book(&mut self, path: &str) -> Result<(), String> {
+        self.book = Some(Arc::new(Book::load(path)?));
+        self.book_file = Some(path.to_string());
+        Ok(())
+    }
+
+    // A new engine searching with these settings
+    pub fn engine(&self) -> Engine {
+        let mut engine = Engine::new(self.threads, Arc::clone(&self.eval_params));
+        engine.network = self.network.clone();
+        engine
+    }
+}
+
+fn expect_path(arg: &str, path: Option<String>) -> String {
+    match path {
+        Some(path) => path,
+        None => {
+            eprintln!("{} expects a file", arg);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_usage() {
+    println!("Usage: c-chess [OPTIONS]");
+    println!(
+        "       c-chess tune <POSITIONS> [OPTIONS]  Tune the evaluation weights, see tune --help"
+    );
+    println!(
+        "       c-chess match <OPENINGS> [OPTIONS]  Play two engine settings against each other, see match --help"
+    );
+    println!("       c-chess uci                         Talk the Universal Chess Interface on stdin and stdout");
+    println!();
+    println!("Options:");
+    println!("  -t, --threads <N>              Number of threads Magnus searches with");
+    println!("  -p, --ponder                   Let Magnus think during your turn");
+    println!("  -e, --eval-params <FILE>       Load Magnus' evaluation weights from a file");
+    println!("  -n, --nnue <FILE>              Evaluate with a neural network instead");
+    println!("  -b, --book <FILE>              Let Magnus play openings from a Polyglot book");
+    println!(
+        "  -c, --clock <TC>               Play with clocks, e.g. 5+3 (increment) or 5d3 (delay)"
+    );
+    println!("      --save-eval-params <FILE>  Write the evaluation weights to a file and exit");
+    println!("  -h, --help                     Print this help");
+}
+
+pub fn settings_menu(settings: &mut Settings) {
+    clear_screen();
+    loop {
+        arrow_print("Settings", true);
+        arrow_print(
+            &format!(
+                "(1) Threads: {}\n(2) Pondering: {}\n(3) Evaluation parameters: {}\n(4) Neural network: {}\n(5) Opening book: {}\n(6) Time control: {}\n(0) Back\n",
+                settings.threads,
+                on_off(settings.ponder),
+                settings.eval_params_file.as_deref().unwrap_or("default"),
+                settings.network_file.as_deref().unwrap_or("off"),
+                settings.book_file.as_deref().unwrap_or("off"),
+                settings
+                    .time_control
+                    .map(|control| control.to_string())
+                    .unwrap_or("off".to_string())
+            ),
+            false,
+        );
+
+        match read_number() {
+            Some(0) => return,
+            Some(1) => {
+                let available = std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1);
+                arrow_print(
+                    &format!(
+                        "How many threads should Magnus use? This machine has {}.",
+                        available
+                    ),
+                    true,
+                );
+
+                match read_number() {
+                    Some(n) if n > 0 => settings.threads = n,
+                    _ => arrow_print("Invalid input!", true),
+                }
+            }
+            Some(2) => settings.ponder = !settings.ponder,
+            Some(3) => {
+                arrow_print(
+                    "Which parameter file should Magnus use? Leave empty for the defaults.",
+                    true,
+                );
+                let path = read_path();
+                if path.is_empty() {
+                    settings.eval_params = Arc::new(EvalParams::default());
+                    settings.eval_params_file = None;
+                } else if let Err(err) = settings.load_eval_params(&path) {
+                    arrow_print(&err, true);
+                }
+            }
+            Some(4) => {
+                arrow_print(
+                    "Which network should Magnus use? Leave empty for the handcrafted evaluation.",
+                    true,
+                );
+                let path = read_path();
+                if path.is_empty() {
+                    settings.network = None;
+                    settings.network_file = None;
+                } else if let Err(err) = settings.load_network(&path) {
+                    arrow_print(&err, true);
+                }
+            }
+            Some(5) => {
+                arrow_print(
+                    "Which Polyglot book should Magnus use? Leave empty to play without a book.",
+                    true,
+                );
+                let path = read_path();
+                if path.is_empty() {
+                    settings.book = None;
+                    settings.book_file = None;
+                } else if let Err(err) = settings.load_book(&path) {
+                    arrow_print(&err, true);
+                }
+            }
+            Some(6) => {
+                arrow_print(
+                    "Enter minutes plus bonus seconds, 5+3 for an increment or 5d3 for a delay. Leave empty to play without clocks.",
+                    true,
+                );
+                let text = read_path();
+                if text.is_empty() {
+                    settings.time_control = None;
+                } else {
+                    match TimeControl::parse(&text) {
+                        Ok(control) => settings.time_control = Some(control),
+                        Err(err) => arrow_print(&err, true),
+                    }
+                }
+            }
+            _ => arrow_print("Invalid input!", true),
+        }
+    }
+}
+
+fn on_off(value: bool) -> &'static str {
+    if value {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn read_number() -> Option<usize> {
+    print!("{} ", White.bold().paint(">>>"));
+    std::io::stdout().flush().unwrap();
+    let mut input = String::new();
+    read_line(&mut input);
+
+    input.trim().parse::<usize>().ok()
+}
+
+fn read_path() -> String {
+    print!("{} ", White.bold().paint(">>>"));
+    std::io::stdout().flush().unwrap();
+    let mut input = String::new();
+    read_line(&mut input);
+
+    input.trim().to_string()
+}
@@ -0,0 +1,87 @@
+// Weakened play for casual games: fewer nodes, a noisy evaluation and now and then
+// a move that is a little worse than the best one
+pub const MIN_LEVEL: u32 = 1;
+// The top level plays at full strength
+pub const MAX_LEVEL: u32 = 20;
+// Number of the best root moves the weakened choice is made from
+pub const CANDIDATES: usize = 4;
+// Largest spread between the candidates taken into account, in centipawns
+const MAX_SPREAD: i32 = 100;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Skill {
+    level: u32,
+    // Seeds the evaluation noise, so the same position is misjudged the same way
+    // throughout a game and the transposition table stays consistent
+    seed: u64,
+}
+
+impl Skill {
+    pub fn new(level: u32) -> Self {
+        Skill {
+            level: level.clamp(MIN_LEVEL, MAX_LEVEL),
+            seed: fastrand::u64(..),
+        }
+    }
+
+    pub fn level(&self) -> u32 {
+        self.level
+    }
+
+    pub fn is_full_strength(&self) -> bool {
+        self.level == MAX_LEVEL
+    }
+
+    // Depth to search to, the node limit usually ends the search before that on low levels
+    pub fn depth(&self) -> i32 {
+        (1 + self.level as i32 / 4).min(5)
+    }
+
+    // Nodes a search may visit once its first iteration is done
+    pub fn node_limit(&self) -> Option<u64> {
+        if self.is_full_strength() {
+            return None;
+        }
+        Some((100.0 * 1.5f64.powi(self.level as i32 - 1)) as u64)
+    }
+
+    // Evaluation error in centipawns for a position, at most (20 - level)^2 / 2
+    pub fn noise(&self, hash: u64) -> i32 {
+        let amplitude = (MAX_LEVEL - self.level).pow(2) as i32 / 2;
+        if amplitude == 0 {
+            return 0;
+        }
+
+        // Mix the hash so neighbouring positions get unrelated errors
+        let mut x = hash ^ self.seed;
+        x = (x ^ (x >> 33)).wrapping_mul(0xff51afd7ed558ccd);
+        x ^= x >> 33;
+        (x % (2 * amplitude as u64 + 1)) as i32 - amplitude
+    }
+
+    // Index of the move to play given the scores of the root moves, sorted best first,
+    // favouring better moves more the higher the level is
+    pub fn pick(&self, scores: &[i32]) -> usize {
+        if self.is_full_strength() || scores.len() < 2 {
+            return 0;
+        }
+
+        let candidates = &scores[..scores.len().min(CANDIDATES)];
+        let top = candidates[0];
+        let spread = (top - candidates[candidates.len() - 1]).min(MAX_SPREAD);
+        let weakness = 120 - 2 * self.level as i32;
+
+        let mut best = 0;
+        let mut best_score = i32::MIN;
+        for (i, &score) in candidates.iter().enumerate() {
+            // Worse moves make up some of the difference, plus a random part that grows
+            // with the spread between the candidates
+            let push = (weakness * (top - score) + spread * fastrand::i32(0..weakness)) / 128;
+            if score + push >= best_score {
+                best_score = score + push;
+                best = i;
+            }
+        }
+        best
+    }
+}
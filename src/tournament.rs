@@ -0,0 +1,597 @@
+use crate::*;
+use clock::TimeControl;
+use engine::{Engine, SearchOptions};
+use nnue::Network;
+use params::EvalParams;
+use pgn::{write_pgn, PgnMove};
+use selfplay::{play_game, GameResult, Player};
+use skill::Skill;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+// Headless matches between two engine configurations, to measure whether a change gains Elo
+// Every opening is played twice with the colours swapped, and a sequential probability ratio
+// test (SPRT) ends the match early once the result is clear enough
+
+// Depth engines search to when neither a depth, a time nor a clock is given
+const DEFAULT_DEPTH: i32 = 4;
+
+// How one of the engines searches and evaluates, from a spec like "depth=6,eval=tuned.txt"
+struct EngineSpec {
+    name: String,
+    depth: Option<i32>,
+    move_time: Option<Duration>,
+    skill: Option<u32>,
+    params: Arc<EvalParams>,
+    network: Option<Arc<Network>>,
+    options: SearchOptions,
+}
+
+// Settings of a match, from the command line
+struct MatchOptions {
+    // File of opening positions, one FEN per line
+    openings: String,
+    engines: [EngineSpec; 2],
+    games: usize,
+    // Number of games played at the same time
+    concurrency: usize,
+    time_control: Option<TimeControl>,
+    // Where all games are written to as PGN
+    output: String,
+    // The Elo differences tested against each other and the error rates of the SPRT
+    elo0: f64,
+    elo1: f64,
+    alpha: f64,
+    beta: f64,
+}
+
+// Wins, draws and losses from the first engine's point of view
+#[derive(Default)]
+struct Score {
+    wins: usize,
+    draws: usize,
+    losses: usize,
+}
+
+impl Score {
+    fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    // Points per game
+    fn mean(&self) -> f64 {
+        (self.wins as f64 + self.draws as f64 / 2.0) / self.games() as f64
+    }
+
+    // Points per game and the variance of a single game's points, with half a game of every
+    // result added while one of them hasn't happened yet, so a one-sided match doesn't look
+    // like it has no variance at all
+    fn mean_and_variance(&self) -> (f64, f64) {
+        let mut counts = [self.wins, self.draws, self.losses].map(|count| count as f64);
+        if counts.contains(&0.0) {
+            counts = counts.map(|count| count + 0.5);
+        }
+        let [wins, draws, losses] = counts;
+        let n = wins + draws + losses;
+        let mean = (wins + draws / 2.0) / n;
+        let variance =
+            (wins * (1.0 - mean).powi(2) + draws * (0.5 - mean).powi(2) + losses * mean.powi(2))
+                / n;
+        (mean, variance)
+    }
+
+    // Elo difference with the margin of its 95% confidence interval
+    fn elo(&self) -> (f64, f64) {
+        let (mean, variance) = self.mean_and_variance();
+        let margin = 1.96 * (variance / self.games() as f64).sqrt();
+        let low = score_to_elo(mean - margin);
+        let high = score_to_elo(mean + margin);
+        (score_to_elo(self.mean()), (high - low) / 2.0)
+    }
+
+    // Log-likelihood ratio of the first engine being elo1 rather than elo0 stronger,
+    // using the normal approximation of the game results
+    fn llr(&self, elo0: f64, elo1: f64) -> f64 {
+        let (mean, variance) = self.mean_and_variance();
+        let (score0, score1) = (elo_to_score(elo0), elo_to_score(elo1));
+        (score1 - score0) * (2.0 * mean - score0 - score1) / (2.0 * variance) * self.games() as f64
+    }
+}
+
+fn score_to_elo(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+fn elo_to_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+// Run a match with the arguments following "match"
+pub fn run(args: impl Iterator<Item = String>) {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            print_usage();
+            std::process::exit(1);
+        }
+    };
+
+    let openings = load_openings(&options.openings).unwrap_or_else(|err| exit_with(&err));
+    if openings.is_empty() {
+        exit_with(&format!("No openings in {}", options.openings));
+    }
+    let mut output = std::fs::File::create(&options.output)
+        .unwrap_or_else(|err| exit_with(&format!("Could not write {}: {}", options.output, err)));
+
+    let [first, second] = &options.engines;
+    println!(
+        "{} vs {}, {} games from {} openings",
+        first.name,
+        second.name,
+        options.games,
+        openings.len()
+    );
+
+    let lower = (options.beta / (1.0 - options.alpha)).ln();
+    let upper = ((1.0 - options.beta) / options.alpha).ln();
+    let mut score = Score::default();
+    let mut verdict = None;
+
+    // Workers take the next game to play until all are played or the SPRT has decided,
+    // the games that are still running then are finished but not started anymore
+    let next_game = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel::<(usize, bool, GameResult)>();
+    std::thread::scope(|scope| {
+        for _ in 0..options.concurrency {
+            let sender = sender.clone();
+            let (next_game, stop, openings, options) = (&next_game, &stop, &openings, &options);
+            scope.spawn(move || loop {
+                let game = next_game.fetch_add(1, Ordering::Relaxed);
+                if game >= options.games || stop.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                // Each opening is played by both engines as White before moving on
+                let start = openings[game / 2 % openings.len()];
+                let first_white = game % 2 == 0;
+                let timed = options.time_control.is_some();
+                let [first, second] = [
+                    options.engines[0].player(timed),
+                    options.engines[1].player(timed),
+                ];
+                let players = if first_white {
+                    [&first, &second]
+                } else {
+                    [&second, &first]
+                };
+
+                let result = play_game(players, start, &[], options.time_control, None);
+                if sender.send((game, first_white, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        for (game, first_white, result) in receiver {
+            let first_colour = if first_white {
+                Colour::White
+            } else {
+                Colour::Black
+            };
+            match result.winner {
+                Some(winner) if winner == first_colour => score.wins += 1,
+                Some(_) => score.losses += 1,
+                None => score.draws += 1,
+            }
+
+            let names = if first_white {
+                [&first.name, &second.name]
+            } else {
+                [&second.name, &first.name]
+            };
+            let text = game_pgn(&result, game, names);
+            if let Err(err) = writeln!(output, "{}", text) {
+                exit_with(&format!("Could not write {}: {}", options.output, err));
+            }
+
+            let llr = score.llr(options.elo0, options.elo1);
+            let (elo, margin) = score.elo();
+            println!(
+                "Game {} ({} vs {}): {} by {}, score {} - {} - {}, Elo {:+.1} +/- {:.1}, LLR {:.2} ({:.2}, {:.2})",
+                game + 1,
+                names[0],
+                names[1],
+                pgn_result(result.winner),
+                result.ending.name(),
+                score.wins,
+                score.losses,
+                score.draws,
+                elo,
+                margin,
+                llr,
+                lower,
+                upper
+            );
+
+            if verdict.is_none() {
+                if llr >= upper {
+                    verdict = Some(true);
+                } else if llr <= lower {
+                    verdict = Some(false);
+                }
+                if verdict.is_some() {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+
+    let (elo, margin) = score.elo();
+    println!();
+    println!(
+        "Score of {} vs {}: {} - {} - {} [{:.3}] {} games",
+        first.name,
+        second.name,
+        score.wins,
+        score.losses,
+        score.draws,
+        score.mean(),
+        score.games()
+    );
+    println!("Elo difference: {:+.1} +/- {:.1}", elo, margin);
+    match verdict {
+        Some(true) => println!(
+            "SPRT: H1 accepted, {} is at least {} Elo stronger",
+            first.name, options.elo1
+        ),
+        Some(false) => println!(
+            "SPRT: H0 accepted, {} is not {} Elo stronger",
+            first.name, options.elo1
+        ),
+        None => println!(
+            "SPRT: no decision yet, LLR {:.2} ({:.2}, {:.2})",
+            score.llr(options.elo0, options.elo1),
+            lower,
+            upper
+        ),
+    }
+    println!("Games written to {}", options.output);
+}
+
+impl EngineSpec {
+    // Read a comma separated list of settings like "depth=6,eval=tuned.txt"
+    fn parse(text: &str, name: &str) -> Result<Self, String> {
+        let mut spec = EngineSpec {
+            name: name.to_string(),
+            depth: None,
+            move_time: None,
+            skill: None,
+            params: Arc::new(EvalParams::default()),
+            network: None,
+            options: SearchOptions::default(),
+        };
+
+        for setting in text.split(',').filter(|setting| !setting.is_empty()) {
+            let (key, value) = setting.split_once('=').ok_or(format!(
+                "Expected <KEY>=<VALUE> in the engine settings, got {}",
+                setting
+            ))?;
+            let invalid = || format!("Invalid value for {}: {}", key, value);
+            match key {
+                "name" => spec.name = value.to_string(),
+                "depth" => match value.parse::<i32>() {
+                    Ok(depth) if depth > 0 => spec.depth = Some(depth),
+                    _ => return Err(invalid()),
+                },
+                "time" => match value.parse::<f64>().map(Duration::try_from_secs_f64) {
+                    Ok(Ok(time)) if !time.is_zero() => spec.move_time = Some(time),
+                    _ => return Err(invalid()),
+                },
+                "skill" => match value.parse::<u32>() {
+                    Ok(level) if (skill::MIN_LEVEL..=skill::MAX_LEVEL).contains(&level) => {
+                        spec.skill = Some(level)
+                    }
+                    _ => return Err(invalid()),
+                },
+                "eval" => spec.params = Arc::new(EvalParams::load(value)?),
+                "nnue" => spec.network = Some(Arc::new(Network::load(value)?)),
+                "nmp" | "lmr" | "pvs" | "asp" => {
+                    let enabled = match value {
+                        "1" => true,
+                        "0" => false,
+                        _ => return Err(invalid()),
+                    };
+                    let option = match key {
+                        "nmp" => &mut spec.options.null_move,
+                        "lmr" => &mut spec.options.late_move_reductions,
+                        "pvs" => &mut spec.options.pvs,
+                        _ => &mut spec.options.aspiration_windows,
+                    };
+                    *option = enabled;
+                }
+                _ => return Err(format!("Unknown engine setting: {}", key)),
+            }
+        }
+
+        Ok(spec)
+    }
+
+    // A fresh player, so games played at the same time don't share a transposition table
+    // On the clock (timed) the search is stopped by its time budget rather than a depth
+    fn player(&self, timed: bool) -> Player {
+        let mut engine = Engine::new(1, Arc::clone(&self.params));
+        engine.network = self.network.clone();
+        engine.skill = self.skill.map(Skill::new);
+        engine.options = self.options;
+
+        let depth = match (self.depth, self.move_time) {
+            (Some(depth), _) => depth,
+            (None, Some(_)) => TIMED_DEPTH,
+            (None, None) if timed => TIMED_DEPTH,
+            (None, None) => DEFAULT_DEPTH,
+        };
+        Player {
+            name: self.name.clone(),
+            engine,
+            depth,
+            move_time: self.move_time,
+            book: None,
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<MatchOptions, String> {
+    let mut options = MatchOptions {
+        openings: String::new(),
+        engines: [EngineSpec::parse("", "A")?, EngineSpec::parse("", "B")?],
+        games: 100,
+        concurrency: 1,
+        time_control: None,
+        output: "match.pgn".to_string(),
+        elo0: 0.0,
+        elo1: 5.0,
+        alpha: 0.05,
+        beta: 0.05,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-a" | "--first" => {
+                let text = args.next().ok_or("--first expects engine settings")?;
+                options.engines[0] = EngineSpec::parse(&text, "A")?;
+            }
+            "-b" | "--second" => {
+                let text = args.next().ok_or("--second expects engine settings")?;
+                options.engines[1] = EngineSpec::parse(&text, "B")?;
+            }
+            "-g" | "--games" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if n > 0 => options.games = n,
+                _ => return Err("--games expects a positive number".to_string()),
+            },
+            "-j" | "--concurrency" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if n > 0 => options.concurrency = n,
+                _ => return Err("--concurrency expects a positive number".to_string()),
+            },
+            "-c" | "--clock" => {
+                let text = args
+                    .next()
+                    .ok_or("--clock expects a time control like 1+0.1")?;
+                options.time_control = Some(TimeControl::parse(&text)?);
+            }
+            "-o" | "--output" => options.output = args.next().ok_or("--output expects a file")?,
+            "--sprt" => {
+                let mut elo = || args.next().and_then(|elo| elo.parse::<f64>().ok());
+                match (elo(), elo()) {
+                    (Some(elo0), Some(elo1)) if elo0 < elo1 => {
+                        options.elo0 = elo0;
+                        options.elo1 = elo1;
+                    }
+                    _ => return Err("--sprt expects two Elo values, the lower first".to_string()),
+                }
+            }
+            "--alpha" | "--beta" => match args.next().and_then(|p| p.parse::<f64>().ok()) {
+                Some(p) if p > 0.0 && p < 0.5 => {
+                    if arg == "--alpha" {
+                        options.alpha = p;
+                    } else {
+                        options.beta = p;
+                    }
+                }
+                _ => return Err(format!("{} expects an error rate between 0 and 0.5", arg)),
+            },
+            "-h" | "--help" => {
+                print_usage();
+                std::process::exit(0);
+            }
+            _ if options.openings.is_empty() && !arg.starts_with('-') => options.openings = arg,
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    if options.openings.is_empty() {
+        return Err("No opening file given".to_string());
+    }
+
+    Ok(options)
+}
+
+fn print_usage() {
+    println!("Usage: c-chess match <OPENINGS> [OPTIONS]");
+    println!();
+    println!("Plays engine A against engine B from every opening FEN in the file, once with");
+    println!("each colour, and tests whether A is stronger with an SPRT");
+    println!();
+    println!("Options:");
+    println!("  -a, --first <SETTINGS>    Settings of engine A, see below");
+    println!("  -b, --second <SETTINGS>   Settings of engine B");
+    println!("  -g, --games <N>           Number of games (default 100)");
+    println!("  -j, --concurrency <N>     Games played at the same time (default 1)");
+    println!("  -c, --clock <TC>          Play on the clock, e.g. 1+0.1");
+    println!("  -o, --output <FILE>       Where to write the games as PGN (default match.pgn)");
+    println!("      --sprt <ELO0> <ELO1>  Elo differences the SPRT decides between (default 0 5)");
+    println!("      --alpha <P>           Chance of accepting H1 when H0 is true (default 0.05)");
+    println!("      --beta <P>            Chance of accepting H0 when H1 is true (default 0.05)");
+    println!("  -h, --help                Print this help");
+    println!();
+    println!("Engine settings are comma separated, e.g. depth=6,eval=tuned.txt:");
+    println!("  name=<NAME>    Name in the output and the PGN");
+    println!(
+        "  depth=<N>      Search depth (default {}, unlimited on the clock)",
+        DEFAULT_DEPTH
+    );
+    println!("  time=<SECS>    Time per move");
+    println!("  skill=<LEVEL>  Skill level from 1 to 20");
+    println!("  eval=<FILE>    Evaluation weights");
+    println!("  nnue=<FILE>    Neural network to evaluate with");
+    println!("  nmp=<0|1>      Null move pruning (default 1)");
+    println!("  lmr=<0|1>      Late move reductions (default 1)");
+    println!("  pvs=<0|1>      Principal variation search (default 1)");
+    println!("  asp=<0|1>      Aspiration windows (default 1)");
+}
+
+fn exit_with(err: &str) -> ! {
+    eprintln!("{}", err);
+    std::process::exit(1);
+}
+
+// Read the opening positions, one FEN per line
+fn load_openings(path: &str) -> Result<Vec<(Board, bool)>, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
+    let mut openings = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let opening =
+            fen::parse_fen(line).map_err(|err| format!("Line {}: {}", number + 1, err))?;
+        openings.push(opening);
+    }
+
+    Ok(openings)
+}
+
+// A finished game as PGN, with how it ended as a comment after the last move
+fn game_pgn(result: &GameResult, game: usize, names: [&String; 2]) -> String {
+    let record = &result.record;
+    let mut moves: Vec<PgnMove> = record
+        .moves
+        .iter()
+        .map(|recorded| PgnMove {
+            move_: recorded.move_,
+            nag: None,
+            comment: None,
+        })
+        .collect();
+    if let Some(last) = moves.last_mut() {
+        last.comment = Some(match result.winner {
+            Some(winner) => format!("{} wins by {}", winner.ctos(), result.ending.name()),
+            None => format!("Draw by {}", result.ending.name()),
+        });
+    }
+
+    let tags = [
+        ("Event", "C-Chess match".to_string()),
+        ("Site", "?".to_string()),
+        ("Date", "????.??.??".to_string()),
+        ("Round", (game + 1).to_string()),
+        ("White", names[0].clone()),
+        ("Black", names[1].clone()),
+    ];
+    write_pgn(
+        &tags,
+        record.start,
+        record.start_white,
+        &moves,
+        pgn_result(result.winner),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn score(wins: usize, draws: usize, losses: usize) -> Score {
+        Score {
+            wins,
+            draws,
+            losses,
+        }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 0.01
+    }
+
+    #[test]
+    fn converts_between_score_and_elo() {
+        assert!(close(score_to_elo(0.5), 0.0));
+        assert!(close(score_to_elo(0.75), 190.85));
+        assert!(close(score_to_elo(0.25), -190.85));
+        assert!(close(elo_to_score(190.85), 0.75));
+        for elo in [-300.0, -5.0, 0.0, 42.0, 400.0] {
+            assert!(close(score_to_elo(elo_to_score(elo)), elo));
+        }
+        // A perfect score is a large but finite difference
+        assert!(score_to_elo(1.0).is_finite() && score_to_elo(1.0) > 2000.0);
+    }
+
+    #[test]
+    fn estimates_elo_with_a_shrinking_margin() {
+        let (elo, margin) = score(60, 20, 20).elo();
+        assert!(close(elo, score_to_elo(0.7)));
+        assert!(margin > 0.0);
+
+        let (more_elo, more_margin) = score(600, 200, 200).elo();
+        assert!(close(more_elo, elo));
+        assert!(more_margin < margin / 3.0);
+    }
+
+    #[test]
+    fn adds_half_games_only_while_a_result_is_missing() {
+        let (mean, variance) = score(10, 0, 0).mean_and_variance();
+        assert!(mean < 1.0 && variance > 0.0);
+
+        let (mean, variance) = score(1, 2, 1).mean_and_variance();
+        assert!(close(mean, 0.5));
+        assert!(close(variance, 0.125));
+    }
+
+    #[test]
+    fn sprt_accepts_the_hypothesis_the_results_support() {
+        let bound = (0.95f64 / 0.05).ln();
+        assert!(score(200, 100, 100).llr(0.0, 10.0) > bound);
+        assert!(score(100, 100, 200).llr(0.0, 10.0) < -bound);
+        // Equal results lean towards no gain, but not by much after a few games
+        let even = score(5, 10, 5).llr(0.0, 10.0);
+        assert!(even < 0.0 && even > -bound);
+    }
+
+    #[test]
+    fn parses_engine_settings() {
+        let spec = EngineSpec::parse("name=fast,depth=3,nmp=0,asp=0", "A").unwrap();
+        assert_eq!(spec.name, "fast");
+        assert_eq!(spec.depth, Some(3));
+        assert!(!spec.options.null_move && !spec.options.aspiration_windows);
+        assert!(spec.options.late_move_reductions && spec.options.pvs);
+
+        assert_eq!(spec.player(true).depth, 3);
+        let default = EngineSpec::parse("", "B").unwrap();
+        assert_eq!(default.player(false).depth, DEFAULT_DEPTH);
+        assert_eq!(default.player(true).depth, TIMED_DEPTH);
+
+        for text in [
+            "depth=0", "time=inf", "time=-1", "nmp=2", "speed=9", "depth",
+        ] {
+            assert!(EngineSpec::parse(text, "A").is_err(), "{}", text);
+        }
+    }
+}
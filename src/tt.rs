@@ -0,0 +1,203 @@
+use crate::*;
+use evaluation::MATE_SCORE;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub const DEFAULT_TT_SIZE_MB: usize = 16;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Bound {
+    // The score is exact
+    Exact,
+    // The search failed high, the score is at least this
+    Lower,
+    // The search failed low, the score is at most this
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TtEntry {
+    pub depth: i32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+}
+
+// A slot stores the key XORed with the data, so a torn write from another thread
+// is detected as a key mismatch instead of returning corrupt data
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
+}
+
+// Transposition table shared between all search threads without locking
+pub struct TranspositionTable {
+    slots: Vec<Slot>,
+}
+
+impl TranspositionTable {
+    pub fn new(size_mb: usize) -> Self {
+        let len = (size_mb * 1024 * 1024 / std::mem::size_of::<Slot>()).max(1);
+        let slots = (0..len)
+            .map(|_| Slot {
+                key: AtomicU64::new(0),
+                data: AtomicU64::new(0),
+            })
+            .collect();
+
+        TranspositionTable { slots }
+    }
+
+    fn slot(&self, hash: u64) -> &Slot {
+        &self.slots[(hash % self.slots.len() as u64) as usize]
+    }
+
+    // Look up a position, mate scores are converted back to be relative to the current ply
+    pub fn probe(&self, hash: u64, ply: i32) -> Option<TtEntry> {
+        let slot = self.slot(hash);
+        let data = slot.data.load(Ordering::Relaxed);
+        if slot.key.load(Ordering::Relaxed) ^ data != hash || data == 0 {
+            return None;
+        }
+
+        let mut entry = unpack(data);
+        if entry.score > MATE_SCORE - 1000 {
+            entry.score -= ply;
+        } else if entry.score < -MATE_SCORE + 1000 {
+            entry.score += ply;
+        }
+
+        Some(entry)
+    }
+
+    // Store a search result, always replacing what was in the slot before
+    // Mate scores are stored relative to this position rather than to the root
+    pub fn store(
+        &self,
+        hash: u64,
+        ply: i32,
+        depth: i32,
+        mut score: i32,
+        bound: Bound,
+        best_move: Option<Move>,
+    ) {
+        if score > MATE_SCORE - 1000 {
+            score += ply;
+        } else if score < -MATE_SCORE + 1000 {
+            score -= ply;
+        }
+
+        let data = pack(TtEntry {
+            depth,
+            score,
+            bound,
+            best_move,
+        });
+        let slot = self.slot(hash);
+        slot.key.store(hash ^ data, Ordering::Relaxed);
+        slot.data.store(data, Ordering::Relaxed);
+    }
+}
+
+// Data layout:
+// bits 0-31 score, 32-39 depth, 40-41 bound, 42-53 move, 54 has move
+fn pack(entry: TtEntry) -> u64 {
+    let bound = match entry.bound {
+        Bound::Exact => 1,
+        Bound::Lower => 2,
+        Bound::Upper => 3,
+    };
+
+    let mut data = entry.score as u32 as u64;
+    data |= (entry.depth.clamp(0, 255) as u64) << 32;
+    data |= bound << 40;
+
+    if let Some(((from_row, from_col), (to_row, to_col))) = entry.best_move {
+        let packed_move = from_row | from_col << 3 | to_row << 6 | to_col << 9;
+        data |= (packed_move as u64) << 42;
+        data |= 1 << 54;
+    }
+
+    data
+}
+
+fn unpack(data: u64) -> TtEntry {
+    let bound = match (data >> 40) & 3 {
+        1 => Bound::Exact,
+        2 => Bound::Lower,
+        _ => Bound::Upper,
+    };
+
+    let best_move = if data >> 54 & 1 == 1 {
+        let packed_move = (data >> 42) as usize;
+        Some((
+            (packed_move & 7, packed_move >> 3 & 7),
+            (packed_move >> 6 & 7, packed_move >> 9 & 7),
+        ))
+    } else {
+        None
+    };
+
+    TtEntry {
+        depth: (data >> 32 & 0xFF) as i32,
+        score: data as u32 as i32,
+        bound,
+        best_move,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn same(a: TtEntry, b: TtEntry) -> bool {
+        (a.depth, a.score, a.bound, a.best_move) == (b.depth, b.score, b.bound, b.best_move)
+    }
+
+    #[test]
+    fn unpacks_what_it_packs() {
+        let entries = [
+            (0, 0, Bound::Exact, None),
+            (7, -35, Bound::Lower, Some(((6, 4), (4, 4)))),
+            (255, 1234, Bound::Upper, Some(((7, 7), (0, 0)))),
+            (1, MATE_SCORE - 3, Bound::Exact, Some(((0, 7), (7, 0)))),
+            (12, -MATE_SCORE + 8, Bound::Lower, Some(((3, 5), (2, 6)))),
+        ];
+        for (depth, score, bound, best_move) in entries {
+            let entry = TtEntry {
+                depth,
+                score,
+                bound,
+                best_move,
+            };
+            assert!(same(unpack(pack(entry)), entry), "{:?}", entry);
+        }
+    }
+
+    #[test]
+    fn clamps_the_depth_to_a_byte() {
+        let entry = TtEntry {
+            depth: 300,
+            score: -1,
+            bound: Bound::Upper,
+            best_move: None,
+        };
+        let unpacked = unpack(pack(entry));
+        assert_eq!((unpacked.depth, unpacked.score), (255, -1));
+    }
+
+    #[test]
+    fn stores_mate_scores_relative_to_the_position() {
+        let table = TranspositionTable::new(1);
+        let best_move = Some(((1, 1), (2, 2)));
+        // Mate in 5 plies from a position 3 plies below the root
+        table.store(42, 3, 4, MATE_SCORE - 8, Bound::Exact, best_move);
+
+        let entry = table.probe(42, 3).unwrap();
+        assert_eq!(entry.score, MATE_SCORE - 8);
+        // Reached 6 plies from the root instead, it's still mate in 5 from there
+        assert_eq!(table.probe(42, 6).unwrap().score, MATE_SCORE - 11);
+        assert_eq!(entry.best_move, best_move);
+
+        assert!(table.probe(43, 3).is_none());
+    }
+}
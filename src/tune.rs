@@ -0,0 +1,283 @@
+use crate::*;
+use evaluation::evaluate_board;
+use fen::parse_fen;
+use moves::is_in_check;
+use params::EvalParams;
+
+// Texel's tuning method: find the weights for which the evaluation, mapped to a winning chance,
+// best predicts the results of the games the positions were taken from
+
+// Settings of a tuning run, from the command line
+struct TuneOptions {
+    // File of quiet positions, one FEN and game result per line
+    positions: String,
+    // Where the tuned parameters are written to
+    output: String,
+    // Parameters to start from, the defaults if not given
+    start: Option<String>,
+    threads: usize,
+    // Maximum number of passes over all weights
+    passes: usize,
+}
+
+struct Position {
+    board: Board,
+    // Game result from White's point of view: 1 for a win, 0.5 for a draw and 0 for a loss
+    result: f64,
+}
+
+// Game results in the notations used by common position files
+const RESULTS: [(&str, f64); 11] = [
+    ("1/2-1/2", 0.5),
+    ("1-0", 1.0),
+    ("0-1", 0.0),
+    ("[1.0]", 1.0),
+    ("[0.5]", 0.5),
+    ("[0.0]", 0.0),
+    ("[1]", 1.0),
+    ("[0]", 0.0),
+    ("1.0", 1.0),
+    ("0.5", 0.5),
+    ("0.0", 0.0),
+];
+
+// Run the tuner with the arguments following "tune"
+pub fn run(args: impl Iterator<Item = String>) {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            print_usage();
+            std::process::exit(1);
+        }
+    };
+
+    let mut params = match &options.start {
+        Some(path) => EvalParams::load(path).unwrap_or_else(|err| exit_with(&err)),
+        None => EvalParams::default(),
+    };
+
+    let positions = load_positions(&options.positions).unwrap_or_else(|err| exit_with(&err));
+    if positions.is_empty() {
+        exit_with(&format!("No usable positions in {}", options.positions));
+    }
+    println!("Loaded {} positions", positions.len());
+
+    // The scaling constant is fitted once, so the error only changes with the weights
+    let k = find_scaling(&params, &positions, options.threads);
+    println!("Scaling constant K = {:.3}", k);
+
+    let weights = params
+        .fields_mut()
+        .iter()
+        .map(|(_, values)| values.len())
+        .sum::<usize>();
+    let mut best_error = error(&params, &positions, k, options.threads);
+    println!("Starting error: {:.6}", best_error);
+
+    // Try moving each weight up and down, keeping every change that lowers the error,
+    // and refine the step size once no weight can be improved anymore
+    let mut step = 8;
+    for pass in 1..=options.passes {
+        let mut improved = 0;
+
+        for index in 0..weights {
+            for delta in [step, -step] {
+                *weight(&mut params, index) += delta;
+                params.update_pawn_key();
+                let new_error = error(&params, &positions, k, options.threads);
+
+                if new_error < best_error {
+                    best_error = new_error;
+                    improved += 1;
+                    break;
+                }
+                *weight(&mut params, index) -= delta;
+                params.update_pawn_key();
+            }
+        }
+
+        println!(
+            "Pass {}: step {}, {} weights changed, error {:.6}",
+            pass, step, improved, best_error
+        );
+        // Save after every pass, so a long run can be interrupted without losing progress
+        if let Err(err) = params.save(&options.output) {
+            exit_with(&err);
+        }
+
+        if improved == 0 {
+            if step == 1 {
+                break;
+            }
+            step /= 2;
+        }
+    }
+
+    println!("Tuned parameters written to {}", options.output);
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<TuneOptions, String> {
+    let mut options = TuneOptions {
+        positions: String::new(),
+        output: "tuned.txt".to_string(),
+        start: None,
+        threads: std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1),
+        passes: 100,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" | "--output" => options.output = args.next().ok_or("--output expects a file")?,
+            "-e" | "--eval-params" => {
+                options.start = Some(args.next().ok_or("--eval-params expects a file")?)
+            }
+            "-t" | "--threads" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) if n > 0 => options.threads = n,
+                _ => return Err("--threads expects a positive number".to_string()),
+            },
+            "--passes" => match args.next().and_then(|n| n.parse::<usize>().ok()) {
+                Some(n) => options.passes = n,
+                None => return Err("--passes expects a number".to_string()),
+            },
+            "-h" | "--help" => {
+                print_usage();
+                std::process::exit(0);
+            }
+            _ if options.positions.is_empty() && !arg.starts_with('-') => options.positions = arg,
+            _ => return Err(format!("Unknown argument: {}", arg)),
+        }
+    }
+
+    if options.positions.is_empty() {
+        return Err("No position file given".to_string());
+    }
+
+    Ok(options)
+}
+
+fn print_usage() {
+    println!("Usage: c-chess tune <POSITIONS> [OPTIONS]");
+    println!();
+    println!("Tunes the evaluation weights on a file with one FEN and game result per line,");
+    println!("for example '<FEN> [0.5]' or '<FEN> c9 \"1-0\";'");
+    println!();
+    println!("Options:");
+    println!("  -o, --output <FILE>       Where to write the tuned weights (default tuned.txt)");
+    println!("  -e, --eval-params <FILE>  Weights to start from instead of the defaults");
+    println!("  -t, --threads <N>         Number of threads computing the error");
+    println!("      --passes <N>          Maximum number of passes over all weights (default 100)");
+    println!("  -h, --help                Print this help");
+}
+
+fn exit_with(err: &str) -> ! {
+    eprintln!("{}", err);
+    std::process::exit(1);
+}
+
+// The index-th weight, counting through the parameters in file order
+fn weight(params: &mut EvalParams, index: usize) -> &mut i32 {
+    params
+        .fields_mut()
+        .into_iter()
+        .flat_map(|(_, values)| values)
+        .nth(index)
+        .unwrap()
+}
+
+// Read the positions and results, skipping positions that aren't quiet enough for the
+// static evaluation to make sense
+fn load_positions(path: &str) -> Result<Vec<Position>, String> {
+    let text =
+        std::fs::read_to_string(path).map_err(|err| format!("Could not read {}: {}", path, err))?;
+    let mut positions = Vec::new();
+
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // The result is the first known notation after the position itself
+        let result = RESULTS
+            .iter()
+            .filter_map(|&(notation, result)| line.rfind(notation).map(|i| (i, result)))
+            .filter(|&(i, _)| line[..i].split_whitespace().count() >= 2)
+            .min_by_key(|&(i, _)| i);
+        let (end, result) = match result {
+            Some(result) => result,
+            None => return Err(format!("Line {}: no game result found", number + 1)),
+        };
+
+        let (board, is_white) =
+            parse_fen(&line[..end]).map_err(|err| format!("Line {}: {}", number + 1, err))?;
+
+        if is_in_check(board, is_white) || check_for_mates(board).is_some() {
+            continue;
+        }
+
+        positions.push(Position { board, result });
+    }
+
+    Ok(positions)
+}
+
+// Expected score for White given an evaluation in centipawns
+fn sigmoid(eval: i32, k: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * eval as f64 / 400.0))
+}
+
+// Mean squared difference between the game results and the expected scores
+fn error(params: &EvalParams, positions: &[Position], k: f64, threads: usize) -> f64 {
+    let chunk_size = positions.len().div_ceil(threads);
+
+    let total: f64 = std::thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|position| {
+                            let eval = evaluate_board(position.board, params);
+                            (position.result - sigmoid(eval, k)).powi(2)
+                        })
+                        .sum::<f64>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .sum()
+    });
+
+    total / positions.len() as f64
+}
+
+// Find the K for which the current weights fit the results best, refining the step each round
+fn find_scaling(params: &EvalParams, positions: &[Position], threads: usize) -> f64 {
+    let mut best_k = 1.0;
+    let mut best_error = error(params, positions, best_k, threads);
+
+    for step in [0.1, 0.01, 0.001] {
+        let start = best_k;
+        for i in -10..=10 {
+            let k = start + i as f64 * step;
+            if k <= 0.0 {
+                continue;
+            }
+
+            let new_error = error(params, positions, k, threads);
+            if new_error < best_error {
+                best_error = new_error;
+                best_k = k;
+            }
+        }
+    }
+
+    best_k
+}
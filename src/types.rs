@@ -1,10 +1,16 @@
+use crate::moves::is_in_check;
 use ansi_term::Colour::{Red, White, RGB};
 
+// A move as its (row, col) from and to positions
+pub type Move = ((usize, usize), (usize, usize));
+
 #[derive(Clone, Copy)]
 pub struct Board {
     pub tiles: [[Tile; 8]; 8],
     pub kingpos_w: (usize, usize),
     pub kingpos_b: (usize, usize),
+    // The move that led to this position, highlighted when the board is drawn
+    pub last_move: Option<Move>,
 }
 
 impl Board {
@@ -134,12 +140,30 @@ impl Board {
             tiles: board,
             kingpos_b: (0, 4),
             kingpos_w: (7, 4),
+            last_move: None,
         };
     }
 
     pub fn draw_board(&self, is_white: bool) {
+        self.draw_board_highlighted(is_white, &[]);
+    }
+
+    // Draw the board with a background colour behind the given tiles, the last move and
+    // a king in check are always highlighted
+    pub fn draw_board_highlighted(&self, is_white: bool, highlights: &[(usize, usize)]) {
         let grey = RGB(80, 80, 80);
         let brown = Red;
+        let highlight = RGB(150, 120, 30);
+        let last_move = RGB(60, 90, 120);
+        let check = RGB(170, 30, 30);
+
+        let mut checked = Vec::new();
+        if is_in_check(*self, true) {
+            checked.push(self.kingpos_w);
+        }
+        if is_in_check(*self, false) {
+            checked.push(self.kingpos_b);
+        }
 
         let mut row_id = if is_white { 8 } else { -1 };
         if is_white {
@@ -147,12 +171,12 @@ impl Board {
         } else {
             println!("      h   g   f   e   d   c   b   a");
         }
-        let rows: Vec<&[Tile; 8]> = if is_white {
-            self.tiles.iter().collect()
+        let rows: Vec<(usize, &[Tile; 8])> = if is_white {
+            self.tiles.iter().enumerate().collect()
         } else {
-            self.tiles.iter().rev().collect()
+            self.tiles.iter().enumerate().rev().collect()
         };
-        for row in rows.iter() {
+        for (i, row) in rows.iter() {
             if is_white {
                 row_id -= 1;
             } else {
@@ -164,44 +188,64 @@ impl Board {
                 row_id + 1
             );
 
-            let tiles: Vec<&Tile> = if is_white {
-                row.iter().collect()
+            let tiles: Vec<(usize, &Tile)> = if is_white {
+                row.iter().enumerate().collect()
             } else {
-                row.iter().rev().collect()
+                row.iter().enumerate().rev().collect()
             };
-            for &tile in tiles.iter() {
-                print!("{}", grey.paint("| "));
-                match tile.piece.piece_type {
-                    Type::Empty => match tile.colour {
-                        Colour::Black => print!("{}", brown.paint("·")),
-                        Colour::White => print!("{}", White.paint("·")),
-                    },
-                    Type::Pawn(_) => match tile.piece.colour {
-                        Colour::Black => print!("{}", brown.bold().paint("♙")),
-                        Colour::White => print!("{}", White.bold().paint("♙")),
-                    },
-                    Type::Rook(_) => match tile.piece.colour {
-                        Colour::Black => print!("{}", brown.bold().paint("♖")),
-                        Colour::White => print!("{}", White.bold().paint("♜")),
-                    },
-                    Type::Knight => match tile.piece.colour {
-                        Colour::Black => print!("{}", brown.bold().paint("♘")),
-                        Colour::White => print!("{}", White.bold().paint("♞")),
-                    },
-                    Type::Bishop => match tile.piece.colour {
-                        Colour::Black => print!("{}", brown.bold().paint("♗")),
-                        Colour::White => print!("{}", White.bold().paint("♝")),
-                    },
-                    Type::Queen => match tile.piece.colour {
-                        Colour::Black => print!("{}", brown.bold().paint("♕")),
-                        Colour::White => print!("{}", White.bold().paint("♛")),
-                    },
-                    Type::King(_) => match tile.piece.colour {
-                        Colour::Black => print!("{}", brown.bold().paint("♔")),
-                        Colour::White => print!("{}", White.bold().paint("♚")),
-                    },
+            for &(j, tile) in tiles.iter() {
+                let colour = match tile.piece.piece_type {
+                    Type::Empty => tile.colour,
+                    _ => tile.piece.colour,
+                };
+                let mut style = match colour {
+                    Colour::Black => brown.normal(),
+                    Colour::White => White.normal(),
+                };
+                if tile.piece.piece_type != Type::Empty {
+                    style = style.bold();
+                }
+                let symbol = match (tile.piece.piece_type, tile.piece.colour) {
+                    (Type::Empty, _) => "·",
+                    (Type::Pawn(_), _) => "♙",
+                    (Type::Rook(_), Colour::Black) => "♖",
+                    (Type::Rook(_), Colour::White) => "♜",
+                    (Type::Knight, Colour::Black) => "♘",
+                    (Type::Knight, Colour::White) => "♞",
+                    (Type::Bishop, Colour::Black) => "♗",
+                    (Type::Bishop, Colour::White) => "♝",
+                    (Type::Queen, Colour::Black) => "♕",
+                    (Type::Queen, Colour::White) => "♛",
+                    (Type::King(_), Colour::Black) => "♔",
+                    (Type::King(_), Colour::White) => "♚",
+                };
+
+                let mut padding = grey.normal();
+                let background = if checked.contains(&(*i, j)) {
+                    // Black pieces are red, so the king is drawn white to stay visible
+                    style = White.bold();
+                    Some(check)
+                } else if highlights.contains(&(*i, j)) {
+                    Some(highlight)
+                } else if self
+                    .last_move
+                    .is_some_and(|(from, to)| from == (*i, j) || to == (*i, j))
+                {
+                    Some(last_move)
+                } else {
+                    None
+                };
+                if let Some(background) = background {
+                    style = style.on(background);
+                    padding = padding.on(background);
                 }
-                print!("{}", grey.paint(" "));
+                print!(
+                    "{}{}{}{}",
+                    grey.paint("|"),
+                    padding.paint(" "),
+                    style.paint(symbol),
+                    padding.paint(" ")
+                );
             }
             println!("{}", grey.paint("|"));
         }
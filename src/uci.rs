@@ -0,0 +1,267 @@
+use crate::*;
+use engine::{Engine, SearchHandle};
+use evaluation::MATE_SCORE;
+use input::{read_line, read_line_timeout};
+use params::EvalParams;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// The Universal Chess Interface, so Magnus can be used from chess GUIs and match runners
+// Moves are read and written as <FROM><TO>, followed by "q" for a promotion
+
+// Largest number of threads the Threads option allows
+const MAX_THREADS: usize = 256;
+// Largest number of lines the MultiPV option allows
+const MAX_MULTI_PV: usize = 255;
+
+// A search started by "go" that hasn't reported its best move yet
+struct Search {
+    handle: SearchHandle,
+    board: Board,
+    is_white: bool,
+    // When the search has to be stopped to stay within its time, None to search until "stop"
+    deadline: Option<Instant>,
+}
+
+// Talk UCI on stdin and stdout until "quit" or stdin is closed
+pub fn run() {
+    let mut engine = Engine::new(1, Arc::new(EvalParams::default()));
+    let mut position = (Board::new(), true);
+    let mut search: Option<Search> = None;
+
+    loop {
+        // Poll for input while searching, so the search can be stopped or run out of time
+        let line = match &search {
+            Some(running) => {
+                let out_of_time = running
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline);
+                if running.handle.is_finished() || out_of_time {
+                    finish(search.take().unwrap());
+                    continue;
+                }
+                match read_line_timeout(Duration::from_millis(5)) {
+                    Some(line) => line,
+                    None => continue,
+                }
+            }
+            None => {
+                let mut line = String::new();
+                read_line(&mut line);
+                line
+            }
+        };
+
+        let mut words = line.split_whitespace();
+        match words.next().unwrap_or("") {
+            "uci" => {
+                println!("id name C-Chess Magnus");
+                println!("id author C-Chess");
+                println!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                );
+                println!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTI_PV
+                );
+                println!("uciok");
+            }
+            "isready" => println!("readyok"),
+            "setoption" => {
+                if let Err(err) = set_option(&mut engine, &line) {
+                    println!("info string {}", err);
+                }
+            }
+            "ucinewgame" => {
+                // A new engine starts with an empty transposition table
+                let mut new_engine = Engine::new(engine.threads, Arc::clone(&engine.params));
+                new_engine.network = engine.network.clone();
+                new_engine.multi_pv = engine.multi_pv;
+                engine = new_engine;
+            }
+            "position" => match parse_position(words) {
+                Ok(new_position) => position = new_position,
+                Err(err) => println!("info string {}", err),
+            },
+            "go" => {
+                if let Some(running) = search.take() {
+                    finish(running);
+                }
+                let (board, is_white) = position;
+                let (depth, deadline) = search_limits(words, is_white);
+                search = Some(Search {
+                    handle: engine.start_search(board, depth, is_white),
+                    board,
+                    is_white,
+                    deadline,
+                });
+            }
+            "stop" => {
+                if let Some(running) = search.take() {
+                    finish(running);
+                }
+            }
+            "quit" => std::process::exit(0),
+            _ => {}
+        }
+    }
+}
+
+// Handle "setoption name <NAME> value <VALUE>"
+fn set_option(engine: &mut Engine, line: &str) -> Result<(), String> {
+    let rest = line.trim().strip_prefix("setoption").unwrap_or("").trim();
+    let rest = rest
+        .strip_prefix("name")
+        .ok_or("Expected setoption name <NAME>")?;
+    let (name, value) = match rest.split_once(" value ") {
+        Some((name, value)) => (name.trim(), value.trim()),
+        None => (rest.trim(), ""),
+    };
+
+    match name.to_lowercase().as_str() {
+        "threads" => match value.parse::<usize>() {
+            Ok(n) if (1..=MAX_THREADS).contains(&n) => engine.threads = n,
+            _ => return Err(format!("Invalid value for Threads: {}", value)),
+        },
+        "multipv" => match value.parse::<usize>() {
+            Ok(n) if (1..=MAX_MULTI_PV).contains(&n) => engine.multi_pv = n,
+            _ => return Err(format!("Invalid value for MultiPV: {}", value)),
+        },
+        _ => return Err(format!("Unknown option: {}", name)),
+    }
+    Ok(())
+}
+
+// Handle "position startpos|fen <FEN> [moves <MOVE>...]"
+fn parse_position<'a>(mut words: impl Iterator<Item = &'a str>) -> Result<(Board, bool), String> {
+    let position = match words.next() {
+        Some("startpos") => {
+            // Skip "moves"
+            words.next();
+            (Board::new(), true)
+        }
+        Some("fen") => {
+            // take_while also skips "moves"
+            let fen: Vec<&str> = words.by_ref().take_while(|&word| word != "moves").collect();
+            fen::parse_fen(&fen.join(" "))?
+        }
+        _ => return Err("Expected position startpos or position fen <FEN>".to_string()),
+    };
+    play_moves(position, words)
+}
+
+fn play_moves<'a>(
+    (mut board, mut is_white): (Board, bool),
+    words: impl Iterator<Item = &'a str>,
+) -> Result<(Board, bool), String> {
+    for word in words {
+        let (from, to) = notation::parse_move(board, word, is_white)?;
+        move_piece(&mut board, from, to, is_white)
+            .map_err(|_| format!("'{}' is not a legal move", word))?;
+        is_white = !is_white;
+    }
+    Ok((board, is_white))
+}
+
+// The depth and deadline of a "go" command, from depth, movetime, wtime/btime with their
+// increments, or infinite
+fn search_limits<'a>(
+    mut words: impl Iterator<Item = &'a str>,
+    is_white: bool,
+) -> (i32, Option<Instant>) {
+    let start = Instant::now();
+    let mut depth = None;
+    let mut move_time = None;
+    let mut time = [None; 2];
+    let mut increment = [0; 2];
+
+    while let Some(word) = words.next() {
+        let mut value = || words.next().and_then(|value| value.parse::<i64>().ok());
+        match word {
+            "depth" => depth = value().map(|depth| depth.max(1) as i32),
+            "movetime" => move_time = value(),
+            "wtime" => time[0] = value(),
+            "btime" => time[1] = value(),
+            "winc" => increment[0] = value().unwrap_or(0),
+            "binc" => increment[1] = value().unwrap_or(0),
+            _ => {}
+        }
+    }
+
+    // Like on the clock: a share of the time left plus most of the increment, never more
+    // than half of what is left
+    let side = !is_white as usize;
+    let budget = match (move_time, time[side]) {
+        (Some(ms), _) => Some(ms),
+        (None, Some(left)) => Some((left / 30 + increment[side] * 3 / 4).min(left / 2)),
+        (None, None) => None,
+    };
+    let deadline = budget.map(|ms| start + Duration::from_millis(ms.max(1) as u64));
+
+    match depth {
+        Some(depth) => (depth, deadline),
+        None => (TIMED_DEPTH, deadline),
+    }
+}
+
+// Stop a search if it is still running and report its result
+fn finish(search: Search) {
+    search.handle.stop();
+    let result = search.handle.wait();
+    if let Some(&best_move) = result.pv.first() {
+        // One line per best move with MultiPV, best first
+        if result.lines.len() > 1 {
+            for (i, (score, line)) in result.lines.iter().enumerate() {
+                println!(
+                    "info multipv {} score {} pv {}",
+                    i + 1,
+                    uci_score(*score),
+                    uci_line(search.board, search.is_white, line)
+                );
+            }
+        } else {
+            println!(
+                "info score {} pv {}",
+                uci_score(result.score),
+                uci_line(search.board, search.is_white, &result.pv)
+            );
+        }
+        println!("bestmove {}", uci_move(search.board, best_move));
+    } else {
+        println!("bestmove 0000");
+    }
+}
+
+fn uci_line(mut board: Board, mut is_white: bool, line: &[Move]) -> String {
+    let mut moves = Vec::new();
+    for &(from, to) in line {
+        moves.push(uci_move(board, (from, to)));
+        if move_piece(&mut board, from, to, is_white).is_err() {
+            break;
+        }
+        is_white = !is_white;
+    }
+    moves.join(" ")
+}
+
+// A score as "cp <CENTIPAWNS>" or "mate <MOVES>", negative when the side to move is mated
+fn uci_score(score: i32) -> String {
+    if score.abs() > MATE_SCORE - 1000 {
+        let moves = (MATE_SCORE - score.abs() + 1) / 2;
+        format!("mate {}", if score > 0 { moves } else { -moves })
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+fn uci_move(board: Board, (from, to): Move) -> String {
+    let promotion = matches!(board.tiles[from.0][from.1].piece.piece_type, Type::Pawn(_))
+        && (to.0 == 0 || to.0 == 7);
+    format!(
+        "{}{}{}",
+        reverse_match_input(from),
+        reverse_match_input(to),
+        if promotion { "q" } else { "" }
+    )
+}
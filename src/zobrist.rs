@@ -0,0 +1,75 @@
+use crate::*;
+
+// Random keys for every piece kind on every tile, generated at compile time with xorshift
+static PIECE_KEYS: [[u64; 64]; 16] = generate_keys();
+const BLACK_TO_MOVE_KEY: u64 = 0xF3A5_8C1D_26B7_0E49;
+
+const fn generate_keys() -> [[u64; 64]; 16] {
+    let mut keys = [[0; 64]; 16];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+
+    let mut i = 0;
+    while i < 16 {
+        let mut j = 0;
+        while j < 64 {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            keys[i][j] = state;
+            j += 1;
+        }
+        i += 1;
+    }
+
+    keys
+}
+
+// Kings and rooks that haven't moved get their own keys since they decide castling rights
+fn piece_index(piece: Piece) -> usize {
+    let kind = match piece.piece_type {
+        Type::Pawn(_) => 0,
+        Type::Rook(false) => 1,
+        Type::Rook(true) => 2,
+        Type::Knight => 3,
+        Type::Bishop => 4,
+        Type::Queen => 5,
+        Type::King(false) => 6,
+        Type::King(true) => 7,
+        Type::Empty => unreachable!(),
+    };
+
+    match piece.colour {
+        Colour::White => kind,
+        Colour::Black => kind + 8,
+    }
+}
+
+// Hash a position together with the side to move
+pub fn hash_board(board: &Board, is_white: bool) -> u64 {
+    let mut hash = if is_white { 0 } else { BLACK_TO_MOVE_KEY };
+
+    for (i, row) in board.tiles.iter().enumerate() {
+        for (j, tile) in row.iter().enumerate() {
+            if tile.piece.piece_type != Type::Empty {
+                hash ^= PIECE_KEYS[piece_index(tile.piece)][i * 8 + j];
+            }
+        }
+    }
+
+    hash
+}
+
+// Hash only the pawns, for caching pawn structure evaluation
+pub fn hash_pawns(board: &Board) -> u64 {
+    let mut hash = 0;
+
+    for (i, row) in board.tiles.iter().enumerate() {
+        for (j, tile) in row.iter().enumerate() {
+            if let Type::Pawn(_) = tile.piece.piece_type {
+                hash ^= PIECE_KEYS[piece_index(tile.piece)][i * 8 + j];
+            }
+        }
+    }
+
+    hash
+}